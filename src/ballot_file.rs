@@ -0,0 +1,478 @@
+use std::{collections::HashMap, fs, io::Write, path::Path};
+
+use crate::{
+    core::{Choice, writeable_file},
+    error::{Error, Result},
+};
+
+/// Code of a rank where nothing was marked
+pub const UNDERVOTE: u32 = 0;
+/// Code of a rank where more than one candidate was marked
+pub const OVERVOTE: u32 = 1;
+pub const WRITE_IN: u32 = 2;
+/// Candidates are encoded from this code onwards, in the order of `sorted_cands`
+pub const FIRST_CANDIDATE: u32 = 3;
+
+/// Number of bytes used to store each rank
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum CodeWidth {
+    U8,
+    U16,
+    U32,
+}
+
+impl CodeWidth {
+    /// The narrowest width that can encode every candidate and the reserved codes
+    pub fn for_candidates(n_cands: usize) -> Result<Self> {
+        // without candidates, the highest code is `WRITE_IN`
+        let max_code = (u64::from(FIRST_CANDIDATE) + n_cands as u64).saturating_sub(1);
+        if max_code <= u64::from(u8::MAX) {
+            Ok(CodeWidth::U8)
+        } else if max_code <= u64::from(u16::MAX) {
+            Ok(CodeWidth::U16)
+        } else if max_code <= u64::from(u32::MAX) {
+            Ok(CodeWidth::U32)
+        } else {
+            Err(Error::Encoding(format!(
+                "Too many candidates to encode: {n_cands}"
+            )))
+        }
+    }
+
+    pub fn from_n_bytes(n_bytes: u8) -> Option<Self> {
+        match n_bytes {
+            1 => Some(CodeWidth::U8),
+            2 => Some(CodeWidth::U16),
+            4 => Some(CodeWidth::U32),
+            _ => None,
+        }
+    }
+
+    pub fn n_bytes(self) -> usize {
+        match self {
+            CodeWidth::U8 => 1,
+            CodeWidth::U16 => 2,
+            CodeWidth::U32 => 4,
+        }
+    }
+
+    fn write(self, code: u32, buf: &mut Vec<u8>) {
+        // `for_candidates` guarantees that every code fits
+        match self {
+            CodeWidth::U8 => buf.push(code as u8),
+            CodeWidth::U16 => buf.extend((code as u16).to_le_bytes()),
+            CodeWidth::U32 => buf.extend(code.to_le_bytes()),
+        }
+    }
+
+    fn read(self, bytes: &[u8]) -> u32 {
+        match self {
+            CodeWidth::U8 => u32::from(bytes[0]),
+            CodeWidth::U16 => u32::from(u16::from_le_bytes([bytes[0], bytes[1]])),
+            CodeWidth::U32 => u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+        }
+    }
+}
+
+/// Encodes the ballots as fixed width codes, `ranks` codes per ballot
+pub fn encode_ballots(
+    width: CodeWidth,
+    ranks: usize,
+    all_ballots: &[Vec<Choice>],
+    sorted_cands: &[&str],
+) -> Result<Vec<u8>> {
+    if width.n_bytes() < CodeWidth::for_candidates(sorted_cands.len())?.n_bytes() {
+        return Err(Error::Encoding(format!(
+            "{} candidates cannot be encoded in {} bytes",
+            sorted_cands.len(),
+            width.n_bytes()
+        )));
+    }
+
+    let cand_to_code: HashMap<&str, u32> = sorted_cands
+        .iter()
+        .enumerate()
+        .map(|(idx, cand)| (*cand, FIRST_CANDIDATE + idx as u32))
+        .collect();
+
+    let mut buf = Vec::with_capacity(all_ballots.len() * ranks * width.n_bytes());
+    for ballot in all_ballots {
+        if ballot.len() != ranks {
+            return Err(Error::Encoding(format!(
+                "Ballot has {} ranks instead of {ranks}",
+                ballot.len()
+            )));
+        }
+        for choice in ballot {
+            let code = match choice {
+                Choice::Candidate(cand) => *cand_to_code.get(cand).ok_or_else(|| {
+                    Error::Encoding(format!("{cand} is not in the list of candidates"))
+                })?,
+                Choice::Overvote => OVERVOTE,
+                Choice::Undervote => UNDERVOTE,
+                Choice::WriteIn => WRITE_IN,
+            };
+            width.write(code, &mut buf);
+        }
+    }
+    Ok(buf)
+}
+
+/// Decodes the output of `encode_ballots`, with candidate names borrowed from `sorted_cands`
+pub fn decode_ballots<'a>(
+    width: CodeWidth,
+    ranks: usize,
+    buf: &[u8],
+    sorted_cands: &[&'a str],
+) -> Result<Vec<Vec<Choice<'a>>>> {
+    if ranks == 0 {
+        return Err(Error::Encoding("Ballots have 0 ranks".to_owned()));
+    }
+    let ballot_len = ranks * width.n_bytes();
+    if !buf.len().is_multiple_of(ballot_len) {
+        return Err(Error::Encoding(format!(
+            "{} bytes of ballots is not a multiple of the ballot size of {ballot_len} bytes",
+            buf.len()
+        )));
+    }
+
+    buf.chunks_exact(ballot_len)
+        .map(|ballot| {
+            ballot
+                .chunks_exact(width.n_bytes())
+                .map(|bytes| match width.read(bytes) {
+                    UNDERVOTE => Ok(Choice::Undervote),
+                    OVERVOTE => Ok(Choice::Overvote),
+                    WRITE_IN => Ok(Choice::WriteIn),
+                    code => sorted_cands
+                        .get((code - FIRST_CANDIDATE) as usize)
+                        .map(|cand| Choice::Candidate(cand))
+                        .ok_or_else(|| Error::Encoding(format!("Unknown candidate code {code}"))),
+                })
+                .collect()
+        })
+        .collect()
+}
+
+const MAGIC: &[u8; 8] = b"NYCIRVB\0";
+const VERSION: u16 = 1;
+
+/// A `ballots.bin` file. The layout is, with integers in little endian:
+///
+/// - magic number `NYCIRVB\0`
+/// - format version (u16)
+/// - ranks per ballot (u16)
+/// - code width in bytes (u8)
+/// - contest name length (u32) and contest name
+/// - candidate count (u32), then the length (u32) and name of every candidate
+/// - ballot count (u64), then the ballots encoded by `encode_ballots`
+/// - FNV-1a 64 checksum of everything before it (u64)
+pub struct BallotFile {
+    pub contest: String,
+    pub ranks: usize,
+    pub width: CodeWidth,
+    /// Candidates in the order of their codes
+    pub sorted_cands: Vec<String>,
+    pub n_ballots: usize,
+    codes: Vec<u8>,
+}
+
+impl BallotFile {
+    pub fn new(
+        contest: &str,
+        ranks: usize,
+        all_ballots: &[Vec<Choice>],
+        sorted_cands: &[&str],
+    ) -> Result<Self> {
+        let width = CodeWidth::for_candidates(sorted_cands.len())?;
+        Ok(Self {
+            contest: contest.to_owned(),
+            ranks,
+            width,
+            sorted_cands: sorted_cands.iter().map(|c| c.to_string()).collect(),
+            n_ballots: all_ballots.len(),
+            codes: encode_ballots(width, ranks, all_ballots, sorted_cands)?,
+        })
+    }
+
+    pub fn cands(&self) -> Vec<&str> {
+        self.sorted_cands.iter().map(|c| c.as_str()).collect()
+    }
+
+    pub fn ballots(&self) -> Result<Vec<Vec<Choice<'_>>>> {
+        decode_ballots(self.width, self.ranks, &self.codes, &self.cands())
+    }
+
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let buf = fs::read(path).map_err(|source| Error::File {
+            path: path.to_owned(),
+            source,
+        })?;
+        Self::from_bytes(&buf).map_err(|message| Error::BallotFile {
+            path: path.to_owned(),
+            message,
+        })
+    }
+
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let mut f = writeable_file(path)?;
+        f.write_all(&self.to_bytes()?)
+            .map_err(|source| Error::File {
+                path: path.to_owned(),
+                source,
+            })
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let ranks = u16::try_from(self.ranks)
+            .map_err(|_| Error::Encoding(format!("Too many ranks to encode: {}", self.ranks)))?;
+
+        let mut buf = Vec::with_capacity(self.codes.len() + 1024);
+        buf.extend(MAGIC);
+        buf.extend(VERSION.to_le_bytes());
+        buf.extend(ranks.to_le_bytes());
+        buf.push(self.width.n_bytes() as u8);
+        write_str(&mut buf, &self.contest)?;
+        let n_cands = u32::try_from(self.sorted_cands.len()).map_err(|_| {
+            Error::Encoding(format!(
+                "Too many candidates to encode: {}",
+                self.sorted_cands.len()
+            ))
+        })?;
+        buf.extend(n_cands.to_le_bytes());
+        for cand in &self.sorted_cands {
+            write_str(&mut buf, cand)?;
+        }
+        buf.extend((self.n_ballots as u64).to_le_bytes());
+        buf.extend(&self.codes);
+        let checksum = fnv1a(&buf);
+        buf.extend(checksum.to_le_bytes());
+        Ok(buf)
+    }
+
+    /// Parses a `ballots.bin`, rejecting it if it is truncated, corrupted, or
+    /// from another version of the format, with a message saying why
+    pub fn from_bytes(buf: &[u8]) -> Result<Self, String> {
+        let (content, checksum) = buf
+            .split_last_chunk::<8>()
+            .ok_or_else(|| "ballots.bin is too short".to_owned())?;
+        if fnv1a(content) != u64::from_le_bytes(*checksum) {
+            return Err("ballots.bin checksum does not match, the file is corrupted".to_owned());
+        }
+
+        let mut reader = ByteReader(content);
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err("Not a ballots.bin file".to_owned());
+        }
+        let version = u16::from_le_bytes(reader.take_array()?);
+        if version != VERSION {
+            return Err(format!(
+                "ballots.bin is version {version}, only version {VERSION} is supported"
+            ));
+        }
+        let ranks = u16::from_le_bytes(reader.take_array()?) as usize;
+        let n_bytes = reader.take_array::<1>()?[0];
+        let width = CodeWidth::from_n_bytes(n_bytes)
+            .ok_or_else(|| format!("Invalid code width of {n_bytes} bytes"))?;
+        let contest = reader.take_str()?;
+        let n_cands = u32::from_le_bytes(reader.take_array()?) as usize;
+        let sorted_cands = (0..n_cands)
+            .map(|_| reader.take_str())
+            .collect::<Result<Vec<_>, _>>()?;
+        let too_many_ballots = || "Ballot count in ballots.bin is too large".to_owned();
+        let n_ballots = usize::try_from(u64::from_le_bytes(reader.take_array()?))
+            .map_err(|_| too_many_ballots())?;
+
+        let expected_len = n_ballots
+            .checked_mul(ranks * width.n_bytes())
+            .ok_or_else(too_many_ballots)?;
+        if reader.0.len() != expected_len {
+            return Err(format!(
+                "ballots.bin should have {expected_len} bytes of ballots but has {}",
+                reader.0.len()
+            ));
+        }
+
+        Ok(Self {
+            contest,
+            ranks,
+            width,
+            sorted_cands,
+            n_ballots,
+            codes: reader.0.to_vec(),
+        })
+    }
+}
+
+fn write_str(buf: &mut Vec<u8>, s: &str) -> Result<()> {
+    let len = u32::try_from(s.len())
+        .map_err(|_| Error::Encoding(format!("{s} is too long to encode")))?;
+    buf.extend(len.to_le_bytes());
+    buf.extend(s.as_bytes());
+    Ok(())
+}
+
+struct ByteReader<'a>(&'a [u8]);
+
+impl<'a> ByteReader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+        if self.0.len() < n {
+            return Err("ballots.bin is truncated".to_owned());
+        }
+        let (taken, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn take_array<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+
+    fn take_str(&mut self) -> Result<String, String> {
+        let len = u32::from_le_bytes(self.take_array()?) as usize;
+        String::from_utf8(self.take(len)?.to_vec())
+            .map_err(|_| "ballots.bin has a name that isn't UTF-8".to_owned())
+    }
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CANDS: [&str; 3] = ["Alice A", "Bob B", "Carol C"];
+
+    fn sample_ballots() -> Vec<Vec<Choice<'static>>> {
+        vec![
+            vec![
+                Choice::Candidate("Bob B"),
+                Choice::Overvote,
+                Choice::Candidate("Alice A"),
+            ],
+            vec![Choice::WriteIn, Choice::Undervote, Choice::Undervote],
+            vec![
+                Choice::Candidate("Carol C"),
+                Choice::Candidate("Carol C"),
+                Choice::Candidate("Bob B"),
+            ],
+        ]
+    }
+
+    fn sample_file() -> Vec<u8> {
+        BallotFile::new("DEM Mayor", 3, &sample_ballots(), &CANDS)
+            .and_then(|file| file.to_bytes())
+            .unwrap()
+    }
+
+    /// Replaces the checksum after editing the content, so the edit is what
+    /// gets rejected
+    fn with_checksum(mut content: Vec<u8>) -> Vec<u8> {
+        content.extend(fnv1a(&content).to_le_bytes());
+        content
+    }
+
+    fn assert_rejected(buf: &[u8], message: &str) {
+        match BallotFile::from_bytes(buf) {
+            Err(e) => assert!(e.contains(message), "{e}"),
+            Ok(_) => panic!("accepted an invalid file"),
+        }
+    }
+
+    #[test]
+    fn round_trip_at_every_width() {
+        let ballots = sample_ballots();
+        for width in [CodeWidth::U8, CodeWidth::U16, CodeWidth::U32] {
+            let codes = encode_ballots(width, 3, &ballots, &CANDS).unwrap();
+            assert_eq!(codes.len(), ballots.len() * 3 * width.n_bytes());
+            let decoded = decode_ballots(width, 3, &codes, &CANDS).unwrap();
+            assert_eq!(decoded, ballots);
+        }
+    }
+
+    #[test]
+    fn file_round_trip() {
+        let file = BallotFile::from_bytes(&sample_file()).unwrap();
+        assert_eq!(file.contest, "DEM Mayor");
+        assert_eq!(file.ranks, 3);
+        assert_eq!(file.width, CodeWidth::U8);
+        assert_eq!(file.cands(), CANDS);
+        assert_eq!(file.ballots().unwrap(), sample_ballots());
+    }
+
+    #[test]
+    fn rejects_truncated_file() {
+        let buf = sample_file();
+        assert_rejected(&buf[..buf.len() - 1], "checksum");
+        assert_rejected(&buf[..4], "too short");
+
+        let content = &buf[..buf.len() - 8];
+        assert_rejected(
+            &with_checksum(content[..content.len() - 1].to_vec()),
+            "bytes of ballots",
+        );
+    }
+
+    #[test]
+    fn rejects_flipped_byte() {
+        let mut buf = sample_file();
+        let last_ballot = buf.len() - 9;
+        buf[last_ballot] ^= 1;
+        assert_rejected(&buf, "checksum");
+    }
+
+    #[test]
+    fn rejects_wrong_magic_and_version() {
+        let buf = sample_file();
+        let mut content = buf[..buf.len() - 8].to_vec();
+        content[0] = b'X';
+        assert_rejected(&with_checksum(content), "Not a ballots.bin");
+
+        let mut content = buf[..buf.len() - 8].to_vec();
+        content[MAGIC.len()..MAGIC.len() + 2].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        assert_rejected(&with_checksum(content), "version");
+    }
+
+    #[test]
+    fn read_errors_name_the_file() {
+        let path = std::env::temp_dir().join(format!("nyc-irv-{}-ballots.bin", std::process::id()));
+        fs::write(&path, &sample_file()[..4]).unwrap();
+        let result = BallotFile::read(&path);
+        fs::remove_file(&path).unwrap();
+        match result {
+            Err(Error::BallotFile { path: p, message }) => {
+                assert_eq!(p, path);
+                assert!(message.contains("too short"), "{message}");
+            }
+            Err(e) => panic!("unexpected error {e}"),
+            Ok(_) => panic!("accepted an invalid file"),
+        }
+
+        match BallotFile::read(&path) {
+            Err(Error::File { source, .. }) => {
+                assert_eq!(source.kind(), std::io::ErrorKind::NotFound)
+            }
+            Err(e) => panic!("unexpected error {e}"),
+            Ok(_) => panic!("read a missing file"),
+        }
+    }
+
+    #[test]
+    fn width_boundaries() {
+        let width = |n_cands| CodeWidth::for_candidates(n_cands).unwrap();
+        assert_eq!(width(0), CodeWidth::U8);
+        // codes 3..=255 are 253 candidates
+        assert_eq!(width(253), CodeWidth::U8);
+        assert_eq!(width(254), CodeWidth::U16);
+        assert_eq!(width(65533), CodeWidth::U16);
+        assert_eq!(width(65534), CodeWidth::U32);
+    }
+}
@@ -0,0 +1,443 @@
+use std::path::Path;
+
+use clap::{Args, ValueEnum};
+use nyc_irv::{
+    bootstrap::{BootstrapOptions, BootstrapResult, bootstrap_margins},
+    condorcet::{
+        CondorcetLoserReport, CondorcetResult, LoserStatus, PairwiseMatrix, TieCredit, TopCycle,
+        compute_pairwise_matrix, compute_top_cycle, look_for_condorcet_winner,
+        report_condorcet_losers,
+    },
+    condorcet_methods::{
+        Method, RankedPairsResult, SchulzeResult, Strength, rank_candidates, ranked_pairs, schulze,
+    },
+    core::write_json,
+    election::ElectionConfig,
+    error::{Error, Result},
+    hybrids::{Hybrid, HybridResult, run_hybrid},
+    methods::{BallotMethod, MethodResult, run_method},
+    tabulate::tabulate,
+};
+
+use crate::{read_contest, unique_ballots};
+
+#[derive(Args)]
+pub struct AnalyzeArgs {
+    /// The contest ID, eg 026916
+    pub contest: String,
+    /// An analysis to leave out. Can be given more than once
+    #[arg(long, value_enum)]
+    skip: Vec<Analysis>,
+    /// A method to rank the candidates with from the pairwise matrix, eg
+    /// schulze or kemeny_young. Can be given more than once. Defaults to all of them
+    #[arg(long, value_parser = |name: &str| Method::from_name(name).map_err(|e| e.to_string()))]
+    method: Vec<Method>,
+    /// Resample the ballots this many times to find confidence intervals of the margins
+    #[arg(long)]
+    bootstrap: Option<usize>,
+    /// Seed of the bootstrap resamples
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+    /// Points a pairwise tie is worth in the Copeland scores of the pairwise
+    /// records, which also order the matchups
+    #[arg(long, value_enum, default_value = "half")]
+    tie_credit: TieCreditArg,
+}
+
+#[derive(ValueEnum, Clone, Copy)]
+enum TieCreditArg {
+    Zero,
+    Half,
+    One,
+}
+
+/// The analyses after the Condorcet winner, which is always looked for
+#[derive(ValueEnum, Clone, Copy, PartialEq)]
+enum Analysis {
+    /// The Smith and Schwartz sets, and the cycles in them
+    TopCycle,
+    Schulze,
+    RankedPairs,
+    /// The rankings of every `--method`
+    Methods,
+    /// Every pairwise matchup, and the full pairwise matrix
+    Matchups,
+    /// IRV hybrids that use the pairwise matrix
+    Hybrids,
+    /// Whether the plurality and IRV winners are Condorcet losers
+    CondorcetLosers,
+    /// Borda, Coombs, Baldwin, Nanson, Bucklin and bottom-two-runoff IRV
+    BallotMethods,
+}
+
+/// Writes every analysis as JSON to the contest directory. The hybrids and the
+/// IRV count compared against the Condorcet loser follow the rules of the config
+pub fn analyze(config: &ElectionConfig, args: &AnalyzeArgs) -> Result<()> {
+    let contest_dir = &config.out_dir.join(&args.contest);
+    let ballot_file = read_contest(contest_dir)?;
+    let sorted_cands = ballot_file.cands();
+    let all_ballots = unique_ballots(&ballot_file)?;
+    let run = |analysis| !args.skip.contains(&analysis);
+
+    let matrix = compute_pairwise_matrix(&sorted_cands, &all_ballots);
+
+    let tie_credit = match args.tie_credit {
+        TieCreditArg::Zero => TieCredit::Zero,
+        TieCreditArg::Half => TieCredit::Half,
+        TieCreditArg::One => TieCredit::One,
+    };
+    let condorcet = look_for_condorcet_winner(&matrix, tie_credit);
+    print_condorcet(contest_dir, &condorcet)?;
+
+    if run(Analysis::TopCycle) {
+        let top_cycle = compute_top_cycle(&matrix);
+        print_top_cycle(contest_dir, &top_cycle)?;
+    }
+
+    if run(Analysis::Schulze) {
+        let results =
+            [Strength::Margin, Strength::WinningVotes].map(|strength| schulze(&matrix, strength));
+        print_schulze(contest_dir, &results)?;
+    }
+
+    if run(Analysis::RankedPairs) {
+        let results = [Strength::Margin, Strength::WinningVotes]
+            .map(|strength| ranked_pairs(&matrix, strength));
+        print_ranked_pairs(contest_dir, &results)?;
+    }
+
+    if run(Analysis::Methods) {
+        let methods = if args.method.is_empty() {
+            Method::ALL.as_slice()
+        } else {
+            args.method.as_slice()
+        };
+        compare_methods(contest_dir, &matrix, methods)?;
+    }
+
+    if run(Analysis::Matchups) {
+        // show the candidates in order of their pairwise records
+        let order: Vec<&str> = condorcet.records.iter().map(|record| record.cand).collect();
+        print_pairwise_matchups(contest_dir, &matrix, &order)?;
+    }
+
+    if let Some(n_resamples) = args.bootstrap {
+        let options = BootstrapOptions {
+            n_resamples,
+            seed: args.seed,
+            confidence: 0.95,
+        };
+        let result = bootstrap_margins(&matrix, &all_ballots, &options);
+        print_bootstrap(contest_dir, &result)?;
+    }
+
+    if run(Analysis::Hybrids) {
+        let results: Vec<HybridResult> = Hybrid::ALL
+            .iter()
+            .map(|method| run_hybrid(&matrix, &all_ballots, &config.rules, *method))
+            .collect();
+        print_hybrids(contest_dir, &results)?;
+    }
+
+    if run(Analysis::CondorcetLosers) {
+        let tabulation = tabulate(&sorted_cands, &all_ballots, &config.rules);
+        let report = report_condorcet_losers(&condorcet, &tabulation);
+        print_condorcet_losers(contest_dir, &report)?;
+    }
+
+    if run(Analysis::BallotMethods) {
+        let results: Vec<MethodResult> = BallotMethod::ALL
+            .iter()
+            .map(|method| run_method(&sorted_cands, &all_ballots, *method))
+            .collect();
+        print_ballot_methods(contest_dir, &results)?;
+    }
+
+    Ok(())
+}
+
+fn print_condorcet(out_dir: &Path, condorcet: &CondorcetResult) -> Result<()> {
+    match (condorcet.strict_winner, condorcet.weak_winners.as_slice()) {
+        (Some(winner), _) => println!("{winner} is the Condorcet winner"),
+        (None, []) => println!("No Condorcet winner found, there is a Condorcet cycle"),
+        (None, weak_winners) => println!(
+            "No strict Condorcet winner found, but {} beat or tie every other candidate",
+            weak_winners.join(", ")
+        ),
+    }
+
+    println!(
+        "\nCandidate | Pairwise wins | Pairwise ties | Pairwise losses | Copeland score (tie = {})",
+        condorcet.tie_credit.points()
+    );
+    println!("--- | --- | --- | --- | ---");
+    for record in &condorcet.records {
+        println!(
+            "{} | {} | {} | {} | {}",
+            record.cand, record.wins, record.ties, record.losses, record.copeland_score
+        );
+    }
+
+    write_json(out_dir.join("condorcet.json"), &condorcet)?;
+
+    Ok(())
+}
+
+fn print_top_cycle(out_dir: &Path, top_cycle: &TopCycle) -> Result<()> {
+    println!();
+    println!("Smith set: {}", top_cycle.smith_set.join(", "));
+    println!("Schwartz set: {}", top_cycle.schwartz_set.join(", "));
+    if !top_cycle.cycles.is_empty() {
+        println!("\nCycles in the Smith set:");
+        for cycle in &top_cycle.cycles {
+            println!("{} > {}", cycle.join(" > "), cycle[0]);
+        }
+        if top_cycle.cycles_truncated {
+            println!(
+                "(only the first {} cycles are listed)",
+                top_cycle.cycles.len()
+            );
+        }
+    }
+
+    write_json(out_dir.join("smith_set.json"), &top_cycle)?;
+
+    Ok(())
+}
+
+fn print_schulze(out_dir: &Path, results: &[SchulzeResult]) -> Result<()> {
+    for result in results {
+        println!();
+        match result.winner {
+            Some(winner) => println!("{winner} is the Schulze winner ({:?})", result.strength),
+            None => println!("No unique Schulze winner ({:?})", result.strength),
+        }
+        let places: Vec<String> = result
+            .ranking
+            .iter()
+            .map(|place| place.join(" = "))
+            .collect();
+        println!("Schulze ranking: {}", places.join(" > "));
+    }
+
+    write_json(out_dir.join("schulze.json"), &results)?;
+
+    Ok(())
+}
+
+fn print_ranked_pairs(out_dir: &Path, results: &[RankedPairsResult]) -> Result<()> {
+    for result in results {
+        println!();
+        match result.winner {
+            Some(winner) => println!(
+                "{winner} is the Ranked Pairs winner ({:?})",
+                result.strength
+            ),
+            None => println!("No unique Ranked Pairs winner ({:?})", result.strength),
+        }
+        let places: Vec<String> = result
+            .ranking
+            .iter()
+            .map(|place| place.join(" = "))
+            .collect();
+        println!("Ranked Pairs ranking: {}", places.join(" > "));
+        for pair in result.pairs.iter().filter(|pair| !pair.locked) {
+            println!(
+                "Skipped {} over {} ({}), as it creates a cycle",
+                pair.winner, pair.loser, pair.strength
+            );
+        }
+    }
+
+    write_json(out_dir.join("ranked_pairs.json"), &results)?;
+
+    Ok(())
+}
+
+fn compare_methods(out_dir: &Path, matrix: &PairwiseMatrix, methods: &[Method]) -> Result<()> {
+    println!("\nMethod | Winner | Ranking");
+    println!("--- | --- | ---");
+    let mut rankings = vec![];
+    for method in methods {
+        match rank_candidates(matrix, *method) {
+            Ok(ranking) => {
+                let places: Vec<String> = ranking
+                    .ranking
+                    .iter()
+                    .map(|place| place.join(" = "))
+                    .collect();
+                println!(
+                    "{} | {} | {}",
+                    ranking.method,
+                    ranking.winner.unwrap_or("(tie)"),
+                    places.join(" > ")
+                );
+                rankings.push(ranking);
+            }
+            Err(e) => println!("{} | (skipped: {e}) |", method.name()),
+        }
+    }
+
+    write_json(out_dir.join("methods.json"), &rankings)?;
+
+    Ok(())
+}
+
+fn print_ballot_methods(out_dir: &Path, results: &[MethodResult]) -> Result<()> {
+    println!("\nMethod | Winner | Rounds | Eliminated");
+    println!("--- | --- | --- | ---");
+    for result in results {
+        let eliminated: Vec<String> = result
+            .rounds
+            .iter()
+            .filter(|round| !round.eliminated.is_empty())
+            .map(|round| round.eliminated.join(", "))
+            .collect();
+        println!(
+            "{} | {} | {} | {}",
+            result.method.name(),
+            result.winner.unwrap_or("(none)"),
+            result.rounds.len(),
+            eliminated.join(" → ")
+        );
+    }
+
+    write_json(out_dir.join("ballot_methods.json"), &results)?;
+
+    Ok(())
+}
+
+fn print_bootstrap(out_dir: &Path, result: &BootstrapResult) -> Result<()> {
+    let confidence = result.options.confidence * 100.;
+    println!(
+        "\nCandidate A | Candidate B | Margin | {confidence}% CI of margin | % for A | {confidence}% CI of % for A"
+    );
+    println!("--- | --- | --- | --- | --- | ---");
+    for m in &result.margins {
+        println!(
+            "{} | {} | {} | {:.0} to {:.0} | {:.2}% | {:.2}% to {:.2}%",
+            m.a,
+            m.b,
+            m.margin,
+            m.margin_lower,
+            m.margin_upper,
+            m.share * 100.,
+            m.share_lower * 100.,
+            m.share_upper * 100.
+        );
+    }
+
+    println!("\nCandidate | Probability of being the Condorcet winner");
+    println!("--- | ---");
+    for (cand, p) in &result.condorcet_winner_probability {
+        println!("{cand} | {:.1}%", p * 100.);
+    }
+    println!(
+        "No Condorcet winner | {:.1}%",
+        result.no_condorcet_winner_probability * 100.
+    );
+
+    write_json(out_dir.join("bootstrap.json"), &result)?;
+
+    Ok(())
+}
+
+fn print_hybrids(out_dir: &Path, results: &[HybridResult]) -> Result<()> {
+    println!("\nMethod | Winner | IRV winner | Diverges from IRV in round");
+    println!("--- | --- | --- | ---");
+    for result in results {
+        println!(
+            "{} | {} | {} | {}",
+            result.method.name(),
+            result.winner.unwrap_or("(none)"),
+            result.irv_winner.unwrap_or("(none)"),
+            result
+                .diverges_at_round
+                .map_or("-".to_owned(), |round| round.to_string())
+        );
+    }
+
+    write_json(out_dir.join("hybrids.json"), &results)?;
+
+    Ok(())
+}
+
+fn print_condorcet_losers(out_dir: &Path, report: &CondorcetLoserReport) -> Result<()> {
+    println!();
+    match (report.strict_loser, report.weak_losers.as_slice()) {
+        (Some(loser), _) => println!("{loser} is the Condorcet loser"),
+        (None, []) => println!("No Condorcet loser found"),
+        (None, weak_losers) => println!(
+            "No strict Condorcet loser found, but {} lose to or tie every other candidate",
+            weak_losers.join(", ")
+        ),
+    }
+
+    for (method, winner) in [
+        ("plurality", report.plurality_winner),
+        ("IRV", report.irv_winner),
+    ] {
+        match winner {
+            Some((cand, LoserStatus::StrictLoser)) => {
+                println!("The {method} winner {cand} is the Condorcet loser")
+            }
+            Some((cand, LoserStatus::WeakLoser)) => {
+                println!("The {method} winner {cand} is a weak Condorcet loser")
+            }
+            Some((cand, LoserStatus::NotLoser)) => {
+                println!("The {method} winner {cand} is not a Condorcet loser")
+            }
+            None => (),
+        }
+    }
+
+    for (cand, _) in &report.losers_in_final_round {
+        println!("Condorcet loser {cand} survived to the final IRV round");
+    }
+
+    write_json(out_dir.join("condorcet_loser.json"), &report)?;
+
+    Ok(())
+}
+
+fn print_pairwise_matchups(out_dir: &Path, matrix: &PairwiseMatrix, order: &[&str]) -> Result<()> {
+    println!(
+        "\nCandidate A | Result | Candidate B | Votes for A | Votes for B | % for A | % for B"
+    );
+    println!("--- | --- | --- | --- | --- | --- | ---");
+    for this_cand in order {
+        let this_idx = matrix
+            .index_of(this_cand)
+            .ok_or_else(|| Error::UnknownCandidate(this_cand.to_string()))?;
+        for (other_idx, other_cand) in matrix
+            .cands()
+            .iter()
+            .enumerate()
+            .filter(|(idx, _)| *idx != this_idx)
+        {
+            // get the number of voters that prefers one candidate over the other
+            let n_prefer_this_cand = matrix.get(this_idx, other_idx);
+            let n_prefer_other_cand = matrix.get(other_idx, this_idx);
+
+            let this_perc = matrix.ratio(this_idx, other_idx) * 100.;
+            let other_perc = matrix.ratio(other_idx, this_idx) * 100.;
+
+            let result = if matrix.wins(other_idx, this_idx) {
+                "loses to ❌"
+            } else if matrix.wins(this_idx, other_idx) {
+                "beats ✅"
+            } else {
+                "ties with 🟰"
+            };
+            println!(
+                "{this_cand} | {result} | {other_cand} | {n_prefer_this_cand} | {n_prefer_other_cand} | {this_perc:.2}% | {other_perc:.2}%"
+            );
+        }
+    }
+
+    write_json(out_dir.join("matchups.json"), &matrix.matchups(order))?;
+
+    write_json(out_dir.join("pairwise_matrix.json"), &matrix.dense())?;
+
+    Ok(())
+}
@@ -0,0 +1,70 @@
+use clap::{Args, ValueEnum};
+use nyc_irv::{
+    condorcet::{TieCredit, compute_pairwise_matrix, look_for_condorcet_winner},
+    core::write_json,
+    election::ElectionConfig,
+    error::Result,
+    export::{write_hierarchy, write_later_choices, write_rank_distributions},
+    hierarchy::compute_hierarchy,
+    later_choices::compute_later_choices,
+    rank_distributions::compute_rank_distributions,
+};
+
+use crate::{read_contest, unique_ballots};
+
+#[derive(Args)]
+pub struct ExportArgs {
+    /// The contest ID, eg 026916
+    pub contest: String,
+    /// A data set to leave out. Can be given more than once
+    #[arg(long, value_enum)]
+    skip: Vec<Export>,
+}
+
+#[derive(ValueEnum, Clone, Copy, PartialEq)]
+enum Export {
+    /// `rank-distributions.tsv`
+    RankDistributions,
+    /// `later_choices/`, `flows/`, `sorted_cands.json` and `weighted_matrices.json`
+    LaterChoices,
+    /// `tree.json`
+    Hierarchy,
+    /// `matchups.json`
+    Matchups,
+}
+
+/// Writes the webui data to the contest directory, which the webui can then be pointed at
+pub fn export(config: &ElectionConfig, args: &ExportArgs) -> Result<()> {
+    let contest_dir = &config.out_dir.join(&args.contest);
+    let ballot_file = read_contest(contest_dir)?;
+    let sorted_cands = ballot_file.cands();
+    let all_ballots = unique_ballots(&ballot_file)?;
+    let run = |export| !args.skip.contains(&export);
+
+    // the webui shows the candidates in order of their pairwise records
+    let matrix = compute_pairwise_matrix(&sorted_cands, &all_ballots);
+    let condorcet = look_for_condorcet_winner(&matrix, TieCredit::Half);
+    let order: Vec<&str> = condorcet.records.iter().map(|record| record.cand).collect();
+
+    if run(Export::Matchups) {
+        println!("Writing matchups data");
+        write_json(contest_dir.join("matchups.json"), &matrix.matchups(&order))?;
+    }
+    if run(Export::RankDistributions) {
+        println!("Writing distribution of ranks");
+        let distributions = compute_rank_distributions(&all_ballots, &order);
+        write_rank_distributions(&contest_dir.join("rank-distributions.tsv"), &distributions)?;
+    }
+    if run(Export::LaterChoices) {
+        println!("Writing later choices data");
+        let later_choices = compute_later_choices(&all_ballots, &order);
+        write_later_choices(contest_dir, &later_choices)?;
+    }
+    if run(Export::Hierarchy) {
+        println!("Writing hierarchy data");
+        let hierarchy = compute_hierarchy(&all_ballots);
+        write_hierarchy(&contest_dir.join("tree.json"), &hierarchy)?;
+    }
+
+    Ok(())
+}
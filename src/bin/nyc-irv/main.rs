@@ -0,0 +1,142 @@
+mod analyze;
+mod export;
+mod parse;
+mod tabulate;
+
+use std::{
+    collections::HashSet,
+    io,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    time::Instant,
+};
+
+use clap::{Parser, Subcommand};
+use nyc_irv::{
+    ballot_file::BallotFile,
+    core::{Choice, WeightedBallot, count_unique_ballots},
+    election::ElectionConfig,
+    error::{Error, Result},
+};
+
+/// Extracts ranked contests from NYC cast vote records, then counts and analyses them
+#[derive(Parser)]
+#[command(version)]
+struct Cli {
+    /// TOML file describing the election, see `elections/`. Defaults to the
+    /// 2025 NYC primary in `./data`
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+    /// Directory with the CVR workbooks and the candidate ID workbook.
+    /// Overrides the directories of `cvr` and `candidates` of the config
+    #[arg(long, global = true)]
+    data_dir: Option<PathBuf>,
+    /// Directory the parsed contests are written to, one directory per
+    /// contest. Overrides `out_dir` of the config
+    #[arg(long, global = true)]
+    out_dir: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Lists every ranked contest in the CVR workbooks
+    Contests,
+    /// Extracts contests from the CVR workbooks into `<out-dir>/<contest ID>/ballots.bin`
+    Parse(parse::ParseArgs),
+    /// Condorcet analyses and alternative methods for a parsed contest
+    Analyze(analyze::AnalyzeArgs),
+    /// Counts a parsed contest with IRV, or with STV when there are several seats
+    Tabulate(tabulate::TabulateArgs),
+    /// Writes the data the webui reads for a parsed contest
+    Export(export::ExportArgs),
+}
+
+fn main() -> ExitCode {
+    match run(Cli::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(cli: Cli) -> Result<()> {
+    let mut config = match &cli.config {
+        Some(path) => ElectionConfig::load(path)?,
+        None => ElectionConfig::default(),
+    };
+    if let Some(data_dir) = &cli.data_dir {
+        config.set_data_dir(data_dir);
+    }
+    if let Some(out_dir) = cli.out_dir {
+        config.out_dir = out_dir;
+    }
+    match &cli.command {
+        Command::Contests => parse::list_contests(&config),
+        Command::Parse(args) => parse::parse(&config, args),
+        Command::Analyze(args) => analyze::analyze(&config, args),
+        Command::Tabulate(args) => tabulate::tabulate(&config, args),
+        Command::Export(args) => export::export(&config, args),
+    }
+}
+
+/// Reads the `ballots.bin` that `parse` wrote to the contest directory
+fn read_contest(contest_dir: &Path) -> Result<BallotFile> {
+    let t1 = Instant::now();
+    let path = contest_dir.join("ballots.bin");
+    let ballot_file = BallotFile::read(&path).map_err(|e| match e {
+        Error::File { path, source } if source.kind() == io::ErrorKind::NotFound => {
+            Error::Usage(format!(
+                "Could not read {}, parse the contest first: {source}",
+                path.display()
+            ))
+        }
+        e => e,
+    })?;
+    println!("Reading unpadded binary took {:?}", t1.elapsed());
+    println!("Contest: {}", ballot_file.contest);
+    Ok(ballot_file)
+}
+
+/// Drops repeated rankings of the same candidate, then collapses identical ballots
+fn unique_ballots(ballot_file: &BallotFile) -> Result<Vec<WeightedBallot<'_>>> {
+    let ranks = ballot_file.ranks;
+    let ballots = ballot_file.ballots()?;
+
+    println!("Read {} candidates", ballot_file.cands().len());
+    println!("Read {} ballots with {ranks} ranks", ballots.len());
+
+    let choice_ballots: Vec<Vec<Choice>> = ballots
+        .into_iter()
+        .map(|ballot| {
+            let mut seen = HashSet::new();
+            let mut res: Vec<Choice> = vec![Choice::Undervote; ranks];
+            let mut idx = 0;
+            for choice in ballot {
+                if let Choice::Candidate(name) = choice {
+                    if !seen.contains(name) {
+                        seen.insert(name);
+                        res[idx] = choice;
+                        idx += 1;
+                    }
+                    // if already inserted, do not increment idx.
+                    // the next choice will be written to the current idx,
+                    // leaving at least 1 Undervote at the end.
+                    // as we want to shift choices so that all Undervotes are at the end.
+                } else {
+                    res[idx] = choice;
+                    idx += 1;
+                }
+            }
+            res
+        })
+        .collect();
+
+    // every analysis only needs to look at each distinct ranking once
+    let all_ballots = count_unique_ballots(choice_ballots);
+    println!("Found {} distinct rankings", all_ballots.len());
+    Ok(all_ballots)
+}
@@ -0,0 +1,277 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::Path,
+    time::Instant,
+};
+
+use clap::Args;
+use indicatif::{ProgressBar, ProgressStyle};
+use nyc_irv::{
+    ballot_file::BallotFile,
+    contest::{Contest, ContestIndexEntry, ContestSelector},
+    core::{Choice, write_json, write_json_pretty},
+    cvr::{Anomaly, ContestBallots, WorkbookBallots, read_ballots, read_candidates, read_contests},
+    data_quality::QualityReport,
+    election::ElectionConfig,
+    error::{Error, Result},
+};
+use rayon::prelude::*;
+
+#[derive(Args)]
+pub struct ParseArgs {
+    /// Extract every ranked contest, reading the workbooks only once
+    #[arg(long)]
+    all: bool,
+    /// A contest ID or a part of the contest name. Can be given more than
+    /// once. Defaults to `contests` of the config
+    #[arg(long)]
+    contest: Vec<String>,
+}
+
+/// Lists every ranked contest in the CVR workbooks
+pub fn list_contests(config: &ElectionConfig) -> Result<()> {
+    let excels = config.cvr_workbooks()?;
+    let bar = progress_bar(excels.len())?;
+
+    let found: Vec<Vec<Contest>> = excels
+        .par_iter()
+        .map(|path| {
+            let contests = read_contests(path, config);
+            bar.inc(1);
+            contests
+        })
+        .collect::<Result<_>>()?;
+    bar.finish();
+
+    let mut seen = HashSet::new();
+    println!("ID | Contest | Ranks");
+    println!("--- | --- | ---");
+    for contest in found.into_iter().flatten() {
+        if seen.insert(contest.id.clone()) {
+            println!(
+                "{} | {} | {}",
+                contest.id,
+                contest.name,
+                contest.columns.len()
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Extracts the selected contests into `<out_dir>/<contest ID>/ballots.bin`,
+/// and writes an index of them to `<out_dir>/contests.json`. Cells that could
+/// not be read are listed in `<out_dir>/<contest ID>/anomalies.json`, and
+/// counts of how the ballots were marked are in `quality.json` and `quality.txt`
+pub fn parse(config: &ElectionConfig, args: &ParseArgs) -> Result<()> {
+    let mut selectors: Vec<ContestSelector> = args
+        .contest
+        .iter()
+        .map(|selector| ContestSelector::parse(selector))
+        .collect();
+    if args.all {
+        selectors.push(ContestSelector::All);
+    }
+    if selectors.is_empty() {
+        selectors = config.selectors();
+    }
+    if selectors.is_empty() {
+        return Err(Error::Usage(
+            "Select contests with --contest or --all, or with `contests` in the config".to_owned(),
+        ));
+    }
+
+    let candidates = read_candidates(config)?;
+
+    eprintln!("Total {} candidates in all elections", candidates.len());
+
+    let excels = config.cvr_workbooks()?;
+    let bar = progress_bar(excels.len())?;
+
+    let found: Vec<WorkbookBallots> = excels
+        .par_iter()
+        .map(|path| {
+            // this message is misleading because it looks like we're processing files
+            // sequentially, rather than in parallel
+            // bar.set_message(format!("reading {path:?}"));
+
+            let workbook = read_ballots(path, config, &selectors, &candidates);
+            bar.inc(1);
+            workbook
+        })
+        .collect::<Result<_>>()?;
+
+    bar.finish();
+
+    // workbooks can have different contests, so merge them by contest ID
+    let mut contests: Vec<(Contest, Vec<Vec<Choice>>, QualityReport)> = vec![];
+    let mut anomalies: Vec<Anomaly> = vec![];
+    for workbook in found {
+        anomalies.extend(workbook.anomalies);
+        for ContestBallots {
+            contest,
+            ballots,
+            quality,
+        } in workbook.contests
+        {
+            let idx = match contests.iter().position(|(c, _, _)| c.id == contest.id) {
+                Some(idx) => idx,
+                None => {
+                    let report =
+                        QualityReport::new(&contest.id, &contest.name, contest.columns.len());
+                    contests.push((contest, vec![], report));
+                    contests.len() - 1
+                }
+            };
+            let (_, all_ballots, report) = &mut contests[idx];
+            all_ballots.extend(ballots);
+            report.add_source(workbook.path.clone(), quality);
+        }
+    }
+
+    if contests.is_empty() {
+        return Err(Error::Usage(
+            "No contests matched the selection, see them with `nyc-irv contests`".to_owned(),
+        ));
+    }
+
+    // a contest that cannot be written is reported, and the others still are
+    let mut index = vec![];
+    let mut failed = vec![];
+    for (contest, all_ballots, report) in contests {
+        let contest_anomalies: Vec<&Anomaly> = anomalies
+            .iter()
+            .filter(|anomaly| anomaly.contest == contest.id)
+            .collect();
+        match write_contest(config, &contest, all_ballots, &contest_anomalies, &report) {
+            Ok(entry) => index.push(entry),
+            Err(e) => {
+                eprintln!(
+                    "Error: could not write {} ({}): {e}",
+                    contest.name, contest.id
+                );
+                failed.push(contest.id);
+            }
+        }
+    }
+
+    println!("Writing index of {} contests", index.len());
+    write_json(config.out_dir.join("contests.json"), &index)?;
+
+    if !failed.is_empty() {
+        return Err(Error::FailedContests(failed));
+    }
+    Ok(())
+}
+
+/// Writes the ballots, anomalies and data-quality report of one contest to
+/// `<out_dir>/<contest ID>`
+fn write_contest(
+    config: &ElectionConfig,
+    contest: &Contest,
+    all_ballots: Vec<Vec<Choice>>,
+    anomalies: &[&Anomaly],
+    report: &QualityReport,
+) -> Result<ContestIndexEntry> {
+    println!(
+        "Found {} ballots total for {} ({})",
+        all_ballots.len(),
+        contest.name,
+        contest.id
+    );
+
+    let first_prefs = find_first_prefs(&all_ballots);
+
+    println!("Found {} named candidates", first_prefs.len());
+
+    let contest_dir = config.out_dir.join(&contest.id);
+    fs::create_dir_all(&contest_dir).map_err(|source| Error::File {
+        path: contest_dir.clone(),
+        source,
+    })?;
+
+    println!(
+        "{} cells could not be read and were counted as undervotes",
+        anomalies.len()
+    );
+    write_json_pretty(contest_dir.join("anomalies.json"), &anomalies)?;
+
+    println!("{report}");
+    write_json_pretty(contest_dir.join("quality.json"), &report)?;
+    let path = contest_dir.join("quality.txt");
+    fs::write(&path, report.to_string()).map_err(|source| Error::File { path, source })?;
+
+    let entry = ContestIndexEntry {
+        id: contest.id.clone(),
+        name: contest.name.clone(),
+        ranks: contest.columns.len(),
+        n_ballots: all_ballots.len(),
+        n_candidates: first_prefs.len(),
+        dir: contest_dir.to_string_lossy().into_owned(),
+    };
+
+    let sorted_cands = sort_cands(first_prefs);
+
+    println!("Candidate | First preferences");
+    println!("--- | ---");
+    for (cand, count) in sorted_cands.iter() {
+        println!("{cand} | {count}");
+    }
+
+    let sorted_cands: Vec<&str> = sorted_cands.into_iter().map(|(cand, _)| cand).collect();
+    write_compact_ballots(&contest_dir, contest, all_ballots, sorted_cands)?;
+
+    Ok(entry)
+}
+
+fn progress_bar(len: usize) -> Result<ProgressBar> {
+    Ok(
+        ProgressBar::new(len as u64).with_style(ProgressStyle::with_template(
+            "{wide_bar} {pos}/{len} {msg}",
+        )?),
+    )
+}
+
+/// First preferences of every candidate ranked anywhere on a ballot, so
+/// candidates who are never ranked first have 0
+fn find_first_prefs<'a>(all_ballots: &[Vec<Choice<'a>>]) -> HashMap<&'a str, i32> {
+    let mut first_prefs = HashMap::new();
+    for ballot in all_ballots.iter() {
+        for cand in ballot.iter().filter_map(|choice| choice.candidate()) {
+            first_prefs.entry(cand).or_insert(0);
+        }
+        // skip overvotes and undervotes, then we get the first candidate
+        if let Some(first_pref) = ballot.iter().find_map(|choice| choice.candidate()) {
+            *first_prefs.entry(first_pref).or_insert(0) += 1;
+        }
+    }
+    first_prefs
+}
+
+fn sort_cands(first_prefs: HashMap<&str, i32>) -> Vec<(&str, i32)> {
+    let mut sorted_cands: Vec<_> = first_prefs.into_iter().collect();
+    // candidates without first preferences go last. Sort by name among equal
+    // first preferences, so the order is reproducible
+    sorted_cands.sort_unstable_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then(a.cmp(b)));
+    sorted_cands
+}
+
+fn write_compact_ballots(
+    out_dir: &Path,
+    contest: &Contest,
+    all_ballots: Vec<Vec<Choice>>,
+    sorted_cands: Vec<&str>,
+) -> Result<()> {
+    let t1 = Instant::now();
+    let ballot_file = BallotFile::new(
+        &contest.name,
+        contest.columns.len(),
+        &all_ballots,
+        &sorted_cands,
+    )?;
+    ballot_file.write(out_dir.join("ballots.bin"))?;
+    println!("Unpadded binary: {:?}", t1.elapsed());
+
+    Ok(())
+}
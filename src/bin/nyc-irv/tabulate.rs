@@ -0,0 +1,182 @@
+use std::path::Path;
+
+use clap::{Args, ValueEnum};
+use nyc_irv::{
+    core::write_json,
+    election::ElectionConfig,
+    error::{Error, Result},
+    stv::{StvResult, StvRules, SurplusTransfer, tabulate_stv},
+    tabulate::{Elimination, OvervotePolicy, Rules, Tabulation, TieBreakRule, tabulate as irv},
+};
+
+use crate::{read_contest, unique_ballots};
+
+#[derive(Args)]
+pub struct TabulateArgs {
+    /// The contest ID, eg 026916
+    pub contest: String,
+    /// Eliminate every mathematically defeated candidate at once, or one per
+    /// round. The rules not given here are taken from the config
+    #[arg(long, value_enum)]
+    elimination: Option<EliminationArg>,
+    /// How ties for last place or in the final round are broken
+    #[arg(long, value_enum)]
+    tie_break: Option<TieBreakArg>,
+    /// Seed of the lots drawn to break ties, which implies `--tie-break lot`
+    #[arg(long)]
+    seed: Option<u64>,
+    /// What happens to a ballot when the count reaches an overvoted rank
+    #[arg(long, value_enum)]
+    overvote: Option<OvervoteArg>,
+    /// Exhaust a ballot that skips more than this many consecutive ranks
+    #[arg(long)]
+    max_skipped_ranks: Option<usize>,
+    /// Count with STV for this many seats instead of IRV
+    #[arg(long)]
+    seats: Option<usize>,
+    /// How STV transfers the surplus of an elected candidate
+    #[arg(long, value_parser = |name: &str| SurplusTransfer::from_name(name).map_err(|e| e.to_string()), default_value = "wig")]
+    transfer: SurplusTransfer,
+}
+
+#[derive(ValueEnum, Clone, Copy)]
+enum EliminationArg {
+    Batch,
+    Single,
+}
+
+#[derive(ValueEnum, Clone, Copy)]
+enum TieBreakArg {
+    Lot,
+    FirstPreferences,
+}
+
+#[derive(ValueEnum, Clone, Copy)]
+enum OvervoteArg {
+    ExhaustBallot,
+    SkipRank,
+}
+
+impl TabulateArgs {
+    /// The rules of the config, with the ones given on the command line replaced
+    fn rules(&self, rules: &Rules) -> Rules {
+        let config_seed = match rules.tie_break {
+            TieBreakRule::Lot { seed } => seed,
+            TieBreakRule::FirstPreferences => 0,
+        };
+        Rules {
+            elimination: match self.elimination {
+                Some(EliminationArg::Batch) => Elimination::Batch,
+                Some(EliminationArg::Single) => Elimination::Single,
+                None => rules.elimination,
+            },
+            tie_break: match (self.tie_break, self.seed) {
+                (Some(TieBreakArg::FirstPreferences), _) => TieBreakRule::FirstPreferences,
+                (Some(TieBreakArg::Lot), _) | (None, Some(_)) => TieBreakRule::Lot {
+                    seed: self.seed.unwrap_or(config_seed),
+                },
+                (None, None) => rules.tie_break,
+            },
+            overvote: match self.overvote {
+                Some(OvervoteArg::ExhaustBallot) => OvervotePolicy::ExhaustBallot,
+                Some(OvervoteArg::SkipRank) => OvervotePolicy::SkipRank,
+                None => rules.overvote,
+            },
+            max_skipped_ranks: self.max_skipped_ranks.or(rules.max_skipped_ranks),
+        }
+    }
+}
+
+/// Writes the rounds of the count to `tabulation.json`, or `stv.json` for STV
+pub fn tabulate(config: &ElectionConfig, args: &TabulateArgs) -> Result<()> {
+    if args.seats == Some(0) {
+        return Err(Error::Usage("--seats must be at least 1".to_owned()));
+    }
+
+    let contest_dir = &config.out_dir.join(&args.contest);
+    let ballot_file = read_contest(contest_dir)?;
+    let sorted_cands = ballot_file.cands();
+    let all_ballots = unique_ballots(&ballot_file)?;
+
+    match args.seats {
+        Some(seats) => {
+            let rules = StvRules {
+                seats,
+                transfer: args.transfer,
+            };
+            let stv = tabulate_stv(&sorted_cands, &all_ballots, &rules);
+            print_stv(contest_dir, &stv)?;
+        }
+        None => {
+            println!("Tabulating IRV rounds");
+            let tabulation = irv(&sorted_cands, &all_ballots, &args.rules(&config.rules));
+            print_tabulation(contest_dir, &tabulation)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn print_tabulation(out_dir: &Path, tabulation: &Tabulation) -> Result<()> {
+    println!("\nTabulation rules: {:?}", tabulation.rules);
+    for round in &tabulation.rounds {
+        println!("\nRound {}\n", round.round);
+        println!("Candidate | Votes | %");
+        println!("--- | --- | ---");
+        for (cand, votes) in &round.tallies {
+            let perc = *votes as f32 / round.continuing_ballots as f32 * 100.;
+            println!("{cand} | {votes} | {perc:.2}%");
+        }
+        println!("Exhausted | {} |", round.exhausted_ballots);
+
+        if let Some(tie_break) = &round.tie_break {
+            println!(
+                "\nTie between {:?} broken by choosing {}",
+                tie_break.tied, tie_break.chosen
+            );
+        }
+        if !round.eliminated.is_empty() {
+            println!("\nEliminated: {}", round.eliminated.join(", "));
+        }
+    }
+
+    match tabulation.winner {
+        Some(winner) => println!("\n{winner} is the IRV winner"),
+        None => println!("\nNo IRV winner found, there are no continuing candidates"),
+    }
+
+    write_json(out_dir.join("tabulation.json"), &tabulation)?;
+
+    Ok(())
+}
+
+fn print_stv(out_dir: &Path, stv: &StvResult) -> Result<()> {
+    println!(
+        "\nSTV count for {} seats ({:?})",
+        stv.rules.seats, stv.rules.transfer
+    );
+    for round in &stv.rounds {
+        println!("\nRound {} (quota {:.2})\n", round.round, round.quota);
+        println!("Candidate | Votes");
+        println!("--- | ---");
+        for (cand, votes) in &round.tallies {
+            println!("{cand} | {votes:.2}");
+        }
+        println!("Exhausted | {:.2}", round.exhausted);
+
+        if !round.elected.is_empty() {
+            println!("\nElected: {}", round.elected.join(", "));
+        }
+        if let Some((cand, factor)) = round.surplus_transfer {
+            println!("\nSurplus of {cand} transferred at {factor:.4}");
+        }
+        if !round.eliminated.is_empty() {
+            println!("\nEliminated: {}", round.eliminated.join(", "));
+        }
+    }
+    println!("\nSTV elected: {}", stv.elected.join(", "));
+
+    write_json(out_dir.join("stv.json"), &stv)?;
+
+    Ok(())
+}
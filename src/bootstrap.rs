@@ -0,0 +1,427 @@
+use std::collections::HashMap;
+
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use serde::Serialize;
+
+use crate::{
+    condorcet::{PairwiseMatrix, pairwise_matrix_from_ranked, ranked_indexes},
+    core::{SplitMix64, WeightedBallot},
+};
+
+#[derive(Serialize, Clone, Copy, Debug)]
+pub struct BootstrapOptions {
+    pub n_resamples: usize,
+    pub seed: u64,
+    /// Confidence level of the intervals, eg 0.95
+    pub confidence: f64,
+}
+
+/// The margin of candidate `a` over candidate `b`, and its confidence interval
+#[derive(Serialize)]
+pub struct MarginInterval<'a> {
+    pub a: &'a str,
+    pub b: &'a str,
+    /// Votes for `a` over `b` minus votes for `b` over `a`, in the actual ballots
+    pub margin: i64,
+    pub margin_lower: f64,
+    pub margin_upper: f64,
+    /// Share of the voters expressing a preference between them that prefer `a`
+    pub share: f64,
+    pub share_lower: f64,
+    pub share_upper: f64,
+}
+
+#[derive(Serialize)]
+pub struct BootstrapResult<'a> {
+    pub options: BootstrapOptions,
+    /// Every pair of candidates, with `a` before `b` in candidate order
+    pub margins: Vec<MarginInterval<'a>>,
+    /// Share of the resamples in which each candidate is the Condorcet
+    /// winner, most likely first
+    pub condorcet_winner_probability: Vec<(&'a str, f64)>,
+    /// Share of the resamples without a Condorcet winner
+    pub no_condorcet_winner_probability: f64,
+}
+
+/// Resamples the ballots with replacement `n_resamples` times, and recomputes
+/// the pairwise matrix for each resample. The intervals are percentile intervals
+pub fn bootstrap_margins<'a>(
+    matrix: &PairwiseMatrix<'a>,
+    all_ballots: &[WeightedBallot],
+    options: &BootstrapOptions,
+) -> BootstrapResult<'a> {
+    println!("Bootstrapping {} resamples", options.n_resamples);
+
+    let sorted_cands = matrix.cands();
+    let n_cands = sorted_cands.len();
+    let cand_to_idx: HashMap<&str, usize> = sorted_cands
+        .iter()
+        .enumerate()
+        .map(|(idx, cand)| (*cand, idx))
+        .collect();
+    let ranked: Vec<Vec<usize>> = all_ballots
+        .iter()
+        .map(|ballot| ranked_indexes(ballot, &cand_to_idx))
+        .collect();
+    let counts: Vec<u64> = all_ballots
+        .iter()
+        .map(|ballot| u64::from(ballot.count))
+        .collect();
+
+    // draw the seed of every resample up front, so the results don't depend
+    // on the order the threads run the resamples
+    let mut seeds = SplitMix64::new(options.seed);
+    let resample_seeds: Vec<u64> = (0..options.n_resamples).map(|_| seeds.next_u64()).collect();
+
+    let resamples: Vec<PairwiseMatrix> = resample_seeds
+        .into_par_iter()
+        .map(|seed| {
+            let weights = resample_counts(&mut SplitMix64::new(seed), &counts);
+            pairwise_matrix_from_ranked(sorted_cands, &ranked, &weights)
+        })
+        .collect();
+
+    let mut margins = vec![];
+    for a in 0..n_cands {
+        for b in a + 1..n_cands {
+            let mut resampled_margins: Vec<f64> =
+                resamples.iter().map(|m| m.margin(a, b) as f64).collect();
+            let mut resampled_shares: Vec<f64> = resamples
+                .iter()
+                .map(|m| m.ratio(a, b))
+                .filter(|share| !share.is_nan())
+                .collect();
+            let (margin_lower, margin_upper) =
+                percentile_interval(&mut resampled_margins, options.confidence);
+            let (share_lower, share_upper) =
+                percentile_interval(&mut resampled_shares, options.confidence);
+            margins.push(MarginInterval {
+                a: sorted_cands[a],
+                b: sorted_cands[b],
+                margin: matrix.margin(a, b),
+                margin_lower,
+                margin_upper,
+                share: matrix.ratio(a, b),
+                share_lower,
+                share_upper,
+            });
+        }
+    }
+
+    let mut n_wins = vec![0_usize; n_cands];
+    let mut n_no_winner = 0;
+    for resample in &resamples {
+        let winner = (0..n_cands).find(|a| (0..n_cands).all(|b| *a == b || resample.wins(*a, b)));
+        match winner {
+            Some(winner) => n_wins[winner] += 1,
+            None => n_no_winner += 1,
+        }
+    }
+    let n_resamples = options.n_resamples.max(1) as f64;
+    let mut condorcet_winner_probability: Vec<(&str, f64)> = sorted_cands
+        .iter()
+        .zip(&n_wins)
+        .map(|(cand, n)| (*cand, *n as f64 / n_resamples))
+        .collect();
+    condorcet_winner_probability.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    BootstrapResult {
+        options: *options,
+        margins,
+        condorcet_winner_probability,
+        no_condorcet_winner_probability: n_no_winner as f64 / n_resamples,
+    }
+}
+
+/// Draws how many times each distinct ballot is picked when resampling all the
+/// voters with replacement. The counts are multinomial, drawn as a binomial per
+/// ballot out of the voters not yet drawn
+fn resample_counts(rng: &mut SplitMix64, counts: &[u64]) -> Vec<u32> {
+    let mut voters_left: u64 = counts.iter().sum();
+    let mut draws_left = voters_left;
+    counts
+        .iter()
+        .map(|count| {
+            let drawn = if *count == voters_left {
+                draws_left
+            } else {
+                binomial(rng, draws_left, *count as f64 / voters_left as f64)
+            };
+            voters_left -= count;
+            draws_left -= drawn;
+            drawn as u32
+        })
+        .collect()
+}
+
+/// Draws the number of successes in `n` trials of probability `p`, by inversion
+/// when few successes are expected and with the BTPE algorithm of
+/// Kachitvichyanukul and Schmeiser (1988) otherwise
+fn binomial(rng: &mut SplitMix64, n: u64, p: f64) -> u64 {
+    if p <= 0. || n == 0 {
+        return 0;
+    }
+    if p >= 1. {
+        return n;
+    }
+    // draw the rarer outcome, which keeps inversion short
+    let flipped = p > 0.5;
+    let p = if flipped { 1. - p } else { p };
+    let q = 1. - p;
+    let s = p / q;
+    let a = (n as f64 + 1.) * s;
+
+    let successes = if n as f64 * p < 10. {
+        // walk up the cumulative distribution from 0
+        'draw: loop {
+            let mut u = rng.next_f64();
+            let mut prob = q.powf(n as f64);
+            let mut x = 0;
+            while u > prob {
+                u -= prob;
+                x += 1;
+                // only reached through rounding errors
+                if x > 110 {
+                    continue 'draw;
+                }
+                prob *= a / x as f64 - s;
+            }
+            break x;
+        }
+    } else {
+        let nf = n as f64;
+        let npq = nf * p * q;
+        let f_m = nf * p + p;
+        let m = f_m.floor();
+        // the hat is a triangle around the mode, two parallelograms beside
+        // it and exponential tails
+        let p1 = (2.195 * npq.sqrt() - 4.6 * q).floor() + 0.5;
+        let x_m = m + 0.5;
+        let x_l = x_m - p1;
+        let x_r = x_m + p1;
+        let c = 0.134 + 20.5 / (15.3 + m);
+        let p2 = p1 * (1. + 2. * c);
+        let lambda = |a: f64| a * (1. + 0.5 * a);
+        let lambda_l = lambda((f_m - x_l) / (f_m - x_l * p));
+        let lambda_r = lambda((x_r - f_m) / (x_r * q));
+        let p3 = p2 + c / lambda_l;
+        let p4 = p3 + c / lambda_r;
+
+        let y = loop {
+            let u = rng.next_f64() * p4;
+            let mut v = rng.next_f64();
+            let y = if u <= p1 {
+                break (x_m - p1 * v + u).floor();
+            } else if u <= p2 {
+                let x = x_l + (u - p1) / c;
+                v = v * c + 1. - (x - x_m).abs() / p1;
+                if v > 1. {
+                    continue;
+                }
+                x.floor()
+            } else if u <= p3 {
+                let y = (x_l + v.ln() / lambda_l).floor();
+                if y < 0. {
+                    continue;
+                }
+                v *= (u - p2) * lambda_l;
+                y
+            } else {
+                let y = (x_r - v.ln() / lambda_r).floor();
+                if y > nf {
+                    continue;
+                }
+                v *= (u - p3) * lambda_r;
+                y
+            };
+
+            let k = (y - m).abs();
+            if k <= 20. || k >= 0.5 * npq - 1. {
+                // evaluate f(y) / f(m) with the recurrence of the probabilities
+                let mut f = 1.;
+                let (from, to) = (m.min(y) as u64, m.max(y) as u64);
+                for i in from + 1..=to {
+                    f *= a / i as f64 - s;
+                }
+                if y < m {
+                    f = f.recip();
+                }
+                if v <= f {
+                    break y;
+                }
+                continue;
+            }
+
+            // squeeze with bounds of ln(f(y) / f(m)) before the exact test
+            let rho = (k / npq) * ((k * (k / 3. + 0.625) + 1. / 6.) / npq + 0.5);
+            let t = -0.5 * k * k / npq;
+            let alpha = v.ln();
+            if alpha < t - rho {
+                break y;
+            }
+            if alpha > t + rho {
+                continue;
+            }
+            let x1 = y + 1.;
+            let f1 = m + 1.;
+            let z = nf + 1. - m;
+            let w = nf - y + 1.;
+            let stirling = |a: f64| {
+                let a2 = a * a;
+                (13860. - (462. - (132. - (99. - 140. / a2) / a2) / a2) / a2) / a / 166320.
+            };
+            let bound = x_m * (f1 / x1).ln()
+                + (nf - m + 0.5) * (z / w).ln()
+                + (y - m) * (w * p / (x1 * q)).ln()
+                + stirling(f1)
+                + stirling(z)
+                + stirling(x1)
+                + stirling(w);
+            if alpha <= bound {
+                break y;
+            }
+        };
+        y as u64
+    };
+
+    if flipped { n - successes } else { successes }
+}
+
+/// The interval containing `confidence` of the values, cutting off the same
+/// share on both sides. NaN if there are no values
+fn percentile_interval(values: &mut [f64], confidence: f64) -> (f64, f64) {
+    values.sort_by(f64::total_cmp);
+    let tail = (1. - confidence) / 2.;
+    (percentile(values, tail), percentile(values, 1. - tail))
+}
+
+/// Linearly interpolated percentile of sorted values, `q` between 0 and 1
+fn percentile(sorted: &[f64], q: f64) -> f64 {
+    if sorted.is_empty() {
+        return f64::NAN;
+    }
+    let pos = q.clamp(0., 1.) * (sorted.len() - 1) as f64;
+    let (lower, upper) = (pos.floor() as usize, pos.ceil() as usize);
+    sorted[lower] + (sorted[upper] - sorted[lower]) * (pos - lower as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn binomial_has_the_right_mean_and_variance() {
+        let mut rng = SplitMix64::new(7);
+        let n_draws = 20_000;
+        // inversion, BTPE, and both with p flipped
+        for (n, p) in [
+            (20, 0.3),
+            (1000, 0.005),
+            (1000, 0.5),
+            (1_000_000, 0.3),
+            (50, 0.9),
+        ] {
+            let draws: Vec<f64> = (0..n_draws)
+                .map(|_| binomial(&mut rng, n, p) as f64)
+                .collect();
+            assert!(draws.iter().all(|x| *x <= n as f64));
+            let mean = draws.iter().sum::<f64>() / n_draws as f64;
+            let variance =
+                draws.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n_draws - 1) as f64;
+            let expected_variance = n as f64 * p * (1. - p);
+            let std_error = (expected_variance / n_draws as f64).sqrt();
+            assert!(
+                (mean - n as f64 * p).abs() < 5. * std_error,
+                "mean {mean} for n {n} p {p}"
+            );
+            assert!(
+                (variance / expected_variance - 1.).abs() < 0.1,
+                "variance {variance} for n {n} p {p}"
+            );
+        }
+    }
+
+    /// Pearson's chi-square statistic of the draws against the binomial
+    /// probabilities, with the tails pooled so every bin expects at least 5
+    /// draws, and its degrees of freedom
+    fn chi_square(n: u64, p: f64, draws: &[u64]) -> (f64, usize) {
+        let ln_factorial: Vec<f64> = (0..=n)
+            .scan(0., |total, k| {
+                *total += (k.max(1) as f64).ln();
+                Some(*total)
+            })
+            .collect();
+        let pmf = |k: u64| {
+            let ln_choose = ln_factorial[n as usize]
+                - ln_factorial[k as usize]
+                - ln_factorial[(n - k) as usize];
+            (ln_choose + k as f64 * p.ln() + (n - k) as f64 * (1. - p).ln()).exp()
+        };
+        let mut observed = vec![0_u64; n as usize + 1];
+        for k in draws {
+            observed[*k as usize] += 1;
+        }
+
+        let mut bins: Vec<(f64, f64)> = vec![];
+        let mut bin = (0., 0.);
+        for k in 0..=n {
+            bin.0 += pmf(k) * draws.len() as f64;
+            bin.1 += observed[k as usize] as f64;
+            if bin.0 >= 5. {
+                bins.push(bin);
+                bin = (0., 0.);
+            }
+        }
+        // the upper tail goes into the last bin
+        let last = bins.last_mut().unwrap();
+        last.0 += bin.0;
+        last.1 += bin.1;
+
+        let chi: f64 = bins
+            .iter()
+            .map(|(expected, observed)| (observed - expected).powi(2) / expected)
+            .sum();
+        (chi, bins.len() - 1)
+    }
+
+    #[test]
+    fn binomial_matches_the_probabilities_including_the_tails() {
+        let mut rng = SplitMix64::new(11);
+        // inversion, BTPE near its threshold, and BTPE with p flipped
+        for (n, p) in [
+            (50, 0.1),
+            (1000, 0.004),
+            (30, 0.34),
+            (200, 0.15),
+            (1000, 0.6),
+        ] {
+            let draws: Vec<u64> = (0..100_000).map(|_| binomial(&mut rng, n, p)).collect();
+            let (chi, df) = chi_square(n, p, &draws);
+            // far above the 99.9th percentile of the chi-square distribution
+            let limit = df as f64 + 5. * (2. * df as f64).sqrt();
+            assert!(
+                chi < limit,
+                "chi-square {chi} with {df} degrees of freedom for n {n} p {p}"
+            );
+        }
+    }
+
+    #[test]
+    fn binomial_edge_probabilities() {
+        let mut rng = SplitMix64::new(7);
+        assert_eq!(binomial(&mut rng, 10, 0.), 0);
+        assert_eq!(binomial(&mut rng, 10, 1.), 10);
+        assert_eq!(binomial(&mut rng, 0, 0.5), 0);
+    }
+
+    #[test]
+    fn resample_draws_every_voter() {
+        let counts = [1000, 0, 5, 3, 1];
+        for seed in 0..100 {
+            let weights = resample_counts(&mut SplitMix64::new(seed), &counts);
+            assert_eq!(weights.iter().map(|w| u64::from(*w)).sum::<u64>(), 1009);
+            assert_eq!(weights[1], 0);
+        }
+        assert!(resample_counts(&mut SplitMix64::new(0), &[]).is_empty());
+    }
+}
@@ -1,83 +1,600 @@
-use std::{collections::HashMap, error::Error};
+use std::collections::HashMap;
 
-use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use rayon::iter::{IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator};
+use serde::Serialize;
 
-pub fn look_for_condorcet_winner<'a>(
-    sorted_cands: &[&'a str],
-    matrix: &HashMap<(&str, &str), u32>,
-) -> Result<HashMap<&'a str, i32>, Box<dyn Error>> {
-    eprintln!("Looking for Condorcet winner\n");
-    let mut cands_to_n_wins = HashMap::new();
-    for cand in sorted_cands.iter() {
-        cands_to_n_wins.insert(*cand, 0);
-    }
-    let mut winner_found = false;
-    for this_cand in sorted_cands.iter() {
-        let mut is_cand_possible_cw = true;
-        for other_cand in sorted_cands.iter().filter(|c| *c != this_cand) {
-            let pair1 = (*this_cand, *other_cand);
-            let pair2 = (*other_cand, *this_cand);
-
-            // get the number of voters that prefers one candidate over the other
-            let n_prefer_this_cand = matrix.get(&pair1).ok_or("matrix has no {pair1}")?;
-            let n_prefer_other_cand = matrix.get(&pair2).ok_or("matrix has no {pair2}")?;
-            if n_prefer_other_cand > n_prefer_this_cand {
-                is_cand_possible_cw = false;
-            } else {
-                *cands_to_n_wins.get_mut(*this_cand).unwrap() += 1;
-            }
+use crate::{core::WeightedBallot, tabulate::Tabulation};
+
+/// Number of voters preferring each candidate over each other candidate
+pub struct PairwiseMatrix<'a> {
+    cands: Vec<&'a str>,
+    /// `counts[a * n_cands + b]` is the number of voters preferring candidate `a` over `b`
+    counts: Vec<u32>,
+}
+
+impl<'a> PairwiseMatrix<'a> {
+    /// Candidates in the order of their indexes
+    pub fn cands(&self) -> &[&'a str] {
+        &self.cands
+    }
+
+    pub fn index_of(&self, cand: &str) -> Option<usize> {
+        self.cands.iter().position(|c| *c == cand)
+    }
+
+    /// Number of voters preferring candidate `a` over candidate `b`
+    pub fn get(&self, a: usize, b: usize) -> u32 {
+        self.counts[a * self.cands.len() + b]
+    }
+
+    /// Whether more voters prefer `a` over `b` than `b` over `a`
+    pub fn wins(&self, a: usize, b: usize) -> bool {
+        self.get(a, b) > self.get(b, a)
+    }
+
+    /// Number of voters preferring `a` over `b` minus those preferring `b` over `a`
+    pub fn margin(&self, a: usize, b: usize) -> i64 {
+        i64::from(self.get(a, b)) - i64::from(self.get(b, a))
+    }
+
+    /// Number of voters preferring `a` over `b` if `a` beats `b`, otherwise 0
+    pub fn winning_votes(&self, a: usize, b: usize) -> u32 {
+        if self.wins(a, b) { self.get(a, b) } else { 0 }
+    }
+
+    /// Number of voters that ranked one of `a` and `b` above the other
+    pub fn n_expressing_preference(&self, a: usize, b: usize) -> u32 {
+        self.get(a, b) + self.get(b, a)
+    }
+
+    /// Share of the voters expressing a preference between `a` and `b` that prefer `a`.
+    /// NaN if no voter expressed a preference
+    pub fn ratio(&self, a: usize, b: usize) -> f64 {
+        f64::from(self.get(a, b)) / f64::from(self.n_expressing_preference(a, b))
+    }
+
+    /// Every ordered pair of different candidates, in candidate order, with the
+    /// number of voters preferring the first over the second
+    pub fn iter(&self) -> impl Iterator<Item = (&'a str, &'a str, u32)> + '_ {
+        let n_cands = self.cands.len();
+        (0..n_cands)
+            .flat_map(move |a| (0..n_cands).map(move |b| (a, b)))
+            .filter(|(a, b)| a != b)
+            .map(|(a, b)| (self.cands[a], self.cands[b], self.get(a, b)))
+    }
+
+    /// Every matchup of the candidates in `order` against the other candidates,
+    /// as written to `matchups.json`
+    pub fn matchups(&self, order: &[&str]) -> Vec<Matchup<'a>> {
+        order
+            .iter()
+            .filter_map(|cand| self.index_of(cand))
+            .flat_map(|this_idx| {
+                (0..self.cands.len())
+                    .filter(move |other_idx| *other_idx != this_idx)
+                    .map(move |other_idx| {
+                        Matchup(
+                            self.cands[this_idx],
+                            self.cands[other_idx],
+                            self.get(this_idx, other_idx),
+                            self.get(other_idx, this_idx),
+                        )
+                    })
+            })
+            .collect()
+    }
+
+    /// The matrix between only the given candidates, in the given order. Pairwise
+    /// preferences don't depend on the other candidates, so this is the same as
+    /// the matrix of an election with only these candidates
+    pub fn restrict(&self, cands: &[&'a str]) -> PairwiseMatrix<'a> {
+        let idxs: Vec<usize> = cands
+            .iter()
+            .filter_map(|cand| self.index_of(cand))
+            .collect();
+        PairwiseMatrix {
+            cands: idxs.iter().map(|idx| self.cands[*idx]).collect(),
+            counts: idxs
+                .iter()
+                .flat_map(|a| idxs.iter().map(|b| self.get(*a, *b)))
+                .collect(),
         }
+    }
 
-        if is_cand_possible_cw {
-            println!("{this_cand} is the Condorcet winner");
-            winner_found = true;
+    /// The matrix as nested arrays, with rows and columns in candidate order
+    pub fn dense(&self) -> DenseMatrix<'a> {
+        DenseMatrix {
+            cands: self.cands.clone(),
+            matrix: self
+                .counts
+                .chunks_exact(self.cands.len().max(1))
+                .map(|row| row.to_vec())
+                .collect(),
         }
     }
-    if !winner_found {
-        println!("No Condorcet winner found, there is a Condorcet cycle");
+}
+
+/// Candidate A, candidate B, votes for A over B, votes for B over A
+#[derive(Serialize)]
+pub struct Matchup<'a>(pub &'a str, pub &'a str, pub u32, pub u32);
+
+#[derive(Serialize)]
+pub struct DenseMatrix<'a> {
+    pub cands: Vec<&'a str>,
+    /// `matrix[a][b]` is the number of voters preferring candidate `a` over `b`
+    pub matrix: Vec<Vec<u32>>,
+}
+
+/// How many points a pairwise tie is worth in the Copeland score, where a win is worth 1
+#[derive(Serialize, Clone, Copy, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum TieCredit {
+    Zero,
+    Half,
+    One,
+}
+
+impl TieCredit {
+    pub fn points(self) -> f64 {
+        match self {
+            TieCredit::Zero => 0.,
+            TieCredit::Half => 0.5,
+            TieCredit::One => 1.,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct PairwiseRecord<'a> {
+    pub cand: &'a str,
+    pub wins: usize,
+    pub ties: usize,
+    pub losses: usize,
+    pub copeland_score: f64,
+}
+
+#[derive(Serialize)]
+pub struct CondorcetResult<'a> {
+    /// The candidate that beats every other candidate
+    pub strict_winner: Option<&'a str>,
+    /// Candidates that beat or tie every other candidate. If there is a strict
+    /// winner, it is the only weak winner
+    pub weak_winners: Vec<&'a str>,
+    /// The candidate that loses to every other candidate
+    pub strict_loser: Option<&'a str>,
+    /// Candidates that lose to or tie every other candidate. If there is a strict
+    /// loser, it is the only weak loser
+    pub weak_losers: Vec<&'a str>,
+    pub tie_credit: TieCredit,
+    /// Pairwise record of every candidate, highest Copeland score first
+    pub records: Vec<PairwiseRecord<'a>>,
+}
+
+impl<'a> CondorcetResult<'a> {
+    pub fn loser_status(&self, cand: &str) -> LoserStatus {
+        if self.strict_loser == Some(cand) {
+            LoserStatus::StrictLoser
+        } else if self.weak_losers.contains(&cand) {
+            LoserStatus::WeakLoser
+        } else {
+            LoserStatus::NotLoser
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum LoserStatus {
+    StrictLoser,
+    WeakLoser,
+    NotLoser,
+}
+
+/// Whether the winners of plurality and IRV were Condorcet losers, and whether
+/// a Condorcet loser made it to the final IRV round
+#[derive(Serialize)]
+pub struct CondorcetLoserReport<'a> {
+    pub strict_loser: Option<&'a str>,
+    pub weak_losers: Vec<&'a str>,
+    pub plurality_winner: Option<(&'a str, LoserStatus)>,
+    pub irv_winner: Option<(&'a str, LoserStatus)>,
+    /// Weak or strict Condorcet losers among the candidates of the final round
+    pub losers_in_final_round: Vec<(&'a str, LoserStatus)>,
+}
+
+pub fn report_condorcet_losers<'a>(
+    condorcet: &CondorcetResult<'a>,
+    tabulation: &Tabulation<'a>,
+) -> CondorcetLoserReport<'a> {
+    let with_status = |cand: &'a str| (cand, condorcet.loser_status(cand));
+
+    // the first round is a plurality count
+    let plurality_winner = tabulation
+        .rounds
+        .first()
+        .and_then(|round| round.tallies.first())
+        .map(|(cand, _)| with_status(cand));
+    let losers_in_final_round = tabulation
+        .rounds
+        .last()
+        .map(|round| {
+            round
+                .tallies
+                .iter()
+                .map(|(cand, _)| with_status(cand))
+                .filter(|(_, status)| *status != LoserStatus::NotLoser)
+                .collect()
+        })
+        .unwrap_or_default();
+
+    CondorcetLoserReport {
+        strict_loser: condorcet.strict_loser,
+        weak_losers: condorcet.weak_losers.clone(),
+        plurality_winner,
+        irv_winner: tabulation.winner.map(with_status),
+        losers_in_final_round,
+    }
+}
+
+pub fn look_for_condorcet_winner<'a>(
+    matrix: &PairwiseMatrix<'a>,
+    tie_credit: TieCredit,
+) -> CondorcetResult<'a> {
+    let n_cands = matrix.cands().len();
+    let mut records: Vec<PairwiseRecord> = matrix
+        .cands()
+        .iter()
+        .enumerate()
+        .map(|(this_idx, this_cand)| {
+            let mut record = PairwiseRecord {
+                cand: this_cand,
+                wins: 0,
+                ties: 0,
+                losses: 0,
+                copeland_score: 0.,
+            };
+            for other_idx in (0..n_cands).filter(|idx| *idx != this_idx) {
+                match matrix.margin(this_idx, other_idx) {
+                    m if m > 0 => record.wins += 1,
+                    0 => record.ties += 1,
+                    _ => record.losses += 1,
+                }
+            }
+            record.copeland_score = record.wins as f64 + record.ties as f64 * tie_credit.points();
+            record
+        })
+        .collect();
+
+    let strict_winner = records
+        .iter()
+        .find(|record| record.wins == n_cands - 1)
+        .map(|record| record.cand);
+    let weak_winners = records
+        .iter()
+        .filter(|record| record.losses == 0)
+        .map(|record| record.cand)
+        .collect();
+
+    let strict_loser = records
+        .iter()
+        .find(|record| record.losses == n_cands - 1)
+        .map(|record| record.cand);
+    let weak_losers = records
+        .iter()
+        .filter(|record| record.wins == 0)
+        .map(|record| record.cand)
+        .collect();
+
+    // stable sort, so equal scores are kept in candidate order
+    records.sort_by(|a, b| {
+        b.copeland_score
+            .total_cmp(&a.copeland_score)
+            .then(b.wins.cmp(&a.wins))
+    });
+
+    CondorcetResult {
+        strict_winner,
+        weak_winners,
+        strict_loser,
+        weak_losers,
+        tie_credit,
+        records,
     }
-    Ok(cands_to_n_wins)
 }
 
 pub fn compute_pairwise_matrix<'a>(
     sorted_cands: &[&'a str],
-    all_ballots: &[[Option<&str>; 5]],
-) -> HashMap<(&'a str, &'a str), u32> {
+    all_ballots: &[WeightedBallot],
+) -> PairwiseMatrix<'a> {
     println!("Compute pairwise matrix");
 
-    // key = (cand1, cand2)
-    // value = number of voters preferring cand1 over cand2
-    let mut matrix: HashMap<_, u32> = HashMap::new();
-    for cand1 in sorted_cands.iter() {
-        for cand2 in sorted_cands.iter() {
-            let pair = (*cand1, *cand2);
-
-            let v = all_ballots
-                .par_iter()
-                .map(|ballot| {
-                    let o_cand1_pos = ballot.iter().flatten().position(|cand| cand == cand1);
-                    let o_cand2_pos = ballot.iter().flatten().position(|cand| cand == cand2);
-
-                    // if cand1 is preferred, add `1`. otherwise, no need to add and skip.
-                    // if a candidate has not been ranked, the other candidate is preferred.
-                    // if both candidate is not ranked, skip this voter.
-                    match (o_cand1_pos, o_cand2_pos) {
-                        (Some(_), None) => 1,
-                        (Some(cand1_pos), Some(cand2_pos)) => {
-                            if cand1_pos < cand2_pos {
-                                1
-                            } else {
-                                0
-                            }
+    let cand_to_idx: HashMap<&str, usize> = sorted_cands
+        .iter()
+        .enumerate()
+        .map(|(idx, cand)| (*cand, idx))
+        .collect();
+    let ranked: Vec<Vec<usize>> = all_ballots
+        .iter()
+        .map(|ballot| ranked_indexes(ballot, &cand_to_idx))
+        .collect();
+    let weights: Vec<u32> = all_ballots.iter().map(|ballot| ballot.count).collect();
+
+    pairwise_matrix_from_ranked(sorted_cands, &ranked, &weights)
+}
+
+/// The indexes of the candidates a ballot ranks, in order. A candidate ranked
+/// again lower down only counts at its highest rank
+pub(crate) fn ranked_indexes(
+    ballot: &WeightedBallot,
+    cand_to_idx: &HashMap<&str, usize>,
+) -> Vec<usize> {
+    let mut ranked: Vec<usize> = vec![];
+    for idx in ballot.ranked().filter_map(|cand| cand_to_idx.get(cand)) {
+        if !ranked.contains(idx) {
+            ranked.push(*idx);
+        }
+    }
+    ranked
+}
+
+/// Computes the matrix from the indexes of the candidates each ballot ranked,
+/// in order and without repeats, and the number of voters that cast each ballot
+pub fn pairwise_matrix_from_ranked<'a>(
+    sorted_cands: &[&'a str],
+    ranked_ballots: &[Vec<usize>],
+    weights: &[u32],
+) -> PairwiseMatrix<'a> {
+    let n_cands = sorted_cands.len();
+
+    // every ballot adds to all the pairs it orders, so each ballot is only visited once
+    let counts = ranked_ballots
+        .par_iter()
+        .zip(weights)
+        .fold(
+            || vec![0_u32; n_cands * n_cands],
+            |mut counts, (ranked, weight)| {
+                if *weight == 0 {
+                    return counts;
+                }
+                let mut is_ranked = vec![false; n_cands];
+                for idx in ranked {
+                    is_ranked[*idx] = true;
+                }
+
+                for (pos, preferred) in ranked.iter().enumerate() {
+                    let row = &mut counts[preferred * n_cands..(preferred + 1) * n_cands];
+                    // a candidate is preferred over every candidate ranked after it...
+                    for other in &ranked[pos + 1..] {
+                        row[*other] += weight;
+                    }
+                    // ...and every candidate that has not been ranked.
+                    // if both candidates are not ranked, skip this voter.
+                    for (other, c) in row.iter_mut().enumerate() {
+                        if !is_ranked[other] {
+                            *c += weight;
                         }
-                        _ => 0,
                     }
-                })
-                .sum();
+                }
+                counts
+            },
+        )
+        .reduce(
+            || vec![0_u32; n_cands * n_cands],
+            |mut a, b| {
+                for (x, y) in a.iter_mut().zip(b) {
+                    *x += y;
+                }
+                a
+            },
+        );
+
+    PairwiseMatrix {
+        cands: sorted_cands.to_vec(),
+        counts,
+    }
+}
+
+/// Stop enumerating cycles after this many, as a large top cycle has too many to list
+const MAX_CYCLES: usize = 1000;
+
+#[derive(Serialize)]
+pub struct TopCycle<'a> {
+    /// The smallest set of candidates that all beat every candidate outside of it
+    pub smith_set: Vec<&'a str>,
+    /// The union of the smallest sets of candidates that are not beaten by any
+    /// candidate outside of it. Always a subset of the Smith set
+    pub schwartz_set: Vec<&'a str>,
+    /// Every pairwise cycle among the members of the Smith set, eg `[A, B, C]`
+    /// for A beats B, B beats C and C beats A
+    pub cycles: Vec<Vec<&'a str>>,
+    /// Whether there were more than `MAX_CYCLES` cycles and only the first were listed
+    pub cycles_truncated: bool,
+}
+
+/// Finds the Smith and Schwartz sets, and the cycles among the Smith set.
+/// If there is a Condorcet winner, both sets only contain the winner
+pub fn compute_top_cycle<'a>(matrix: &PairwiseMatrix<'a>) -> TopCycle<'a> {
+    let n_cands = matrix.cands().len();
+
+    // a candidate is in the Smith set if it can reach every other candidate
+    // through a chain of pairwise wins or ties
+    let beats_or_ties = transitive_closure(n_cands, |a, b| matrix.margin(a, b) >= 0);
+    let smith: Vec<usize> = (0..n_cands)
+        .filter(|a| (0..n_cands).all(|b| *a == b || beats_or_ties[*a][b]))
+        .collect();
+
+    // a candidate is in the Schwartz set if every candidate that can reach it
+    // through a chain of pairwise wins can also be reached by it
+    let beats = transitive_closure(n_cands, |a, b| matrix.wins(a, b));
+    let schwartz: Vec<usize> = (0..n_cands)
+        .filter(|a| (0..n_cands).all(|b| !beats[b][*a] || beats[*a][b]))
+        .collect();
+
+    let mut cycles = vec![];
+    let mut cycles_truncated = false;
+    // each cycle is found once, starting from its member with the lowest index
+    for (start_pos, start) in smith.iter().enumerate() {
+        let mut path = vec![*start];
+        find_cycles(
+            matrix,
+            &smith[start_pos + 1..],
+            &mut path,
+            &mut cycles,
+            &mut cycles_truncated,
+        );
+    }
 
-            matrix.entry(pair).and_modify(|c| *c += v).or_insert(v);
+    let to_names = |idxs: &[usize]| idxs.iter().map(|idx| matrix.cands()[*idx]).collect();
+    TopCycle {
+        smith_set: to_names(&smith),
+        schwartz_set: to_names(&schwartz),
+        cycles: cycles.iter().map(|cycle| to_names(cycle)).collect(),
+        cycles_truncated,
+    }
+}
+
+/// `closure[a][b]` is whether `a` reaches `b` through a chain of `edge`s
+fn transitive_closure(n_cands: usize, edge: impl Fn(usize, usize) -> bool) -> Vec<Vec<bool>> {
+    let mut closure: Vec<Vec<bool>> = (0..n_cands)
+        .map(|a| (0..n_cands).map(|b| a != b && edge(a, b)).collect())
+        .collect();
+    // Floyd-Warshall
+    for k in 0..n_cands {
+        for a in 0..n_cands {
+            if !closure[a][k] {
+                continue;
+            }
+            let via_k = closure[k].clone();
+            for (reaches, k_reaches) in closure[a].iter_mut().zip(via_k) {
+                *reaches |= k_reaches;
+            }
+        }
+    }
+    closure
+}
+
+/// Depth first search for every cycle of pairwise wins that starts and ends at
+/// `path[0]`, only going through the candidates in `allowed`
+fn find_cycles(
+    matrix: &PairwiseMatrix,
+    allowed: &[usize],
+    path: &mut Vec<usize>,
+    cycles: &mut Vec<Vec<usize>>,
+    truncated: &mut bool,
+) {
+    let last = path[path.len() - 1];
+    if path.len() > 2 && matrix.wins(last, path[0]) {
+        if cycles.len() == MAX_CYCLES {
+            *truncated = true;
+            return;
         }
+        cycles.push(path.clone());
     }
+    for next in allowed {
+        if *truncated {
+            return;
+        }
+        if !path.contains(next) && matrix.wins(last, *next) {
+            path.push(*next);
+            find_cycles(matrix, allowed, path, cycles, truncated);
+            path.pop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::Choice;
 
-    matrix
+    /// A matrix from ballots like `("A B C", 3)`, naming candidates of `cands`
+    fn matrix<'a>(cands: &[&'a str], ballots: &[(&str, u32)]) -> PairwiseMatrix<'a> {
+        let ballots: Vec<WeightedBallot> = ballots
+            .iter()
+            .map(|(ranking, count)| WeightedBallot {
+                choices: ranking
+                    .split(' ')
+                    .map(|name| {
+                        Choice::Candidate(cands[cands.iter().position(|c| *c == name).unwrap()])
+                    })
+                    .collect(),
+                count: *count,
+            })
+            .collect();
+        compute_pairwise_matrix(cands, &ballots)
+    }
+
+    #[test]
+    fn repeated_ranking_counts_at_highest_rank() {
+        let ballots = [WeightedBallot {
+            choices: vec![
+                Choice::Candidate("A"),
+                Choice::Candidate("B"),
+                Choice::Candidate("A"),
+            ],
+            count: 1,
+        }];
+        let matrix = compute_pairwise_matrix(&["A", "B", "C"], &ballots);
+        assert_eq!(matrix.get(0, 1), 1);
+        assert_eq!(matrix.get(1, 0), 0);
+        assert_eq!(matrix.get(0, 0), 0);
+        assert_eq!(matrix.get(1, 2), 1);
+    }
+
+    #[test]
+    fn rock_paper_scissors_is_the_top_cycle_above_a_condorcet_loser() {
+        let matrix = matrix(
+            &["R", "P", "S", "L"],
+            &[("R P S L", 1), ("P S R L", 1), ("S R P L", 1)],
+        );
+        let top_cycle = compute_top_cycle(&matrix);
+        assert_eq!(top_cycle.smith_set, ["R", "P", "S"]);
+        assert_eq!(top_cycle.schwartz_set, ["R", "P", "S"]);
+        assert_eq!(top_cycle.cycles, [["R", "P", "S"]]);
+        assert!(!top_cycle.cycles_truncated);
+    }
+
+    #[test]
+    fn pairwise_tie_makes_the_schwartz_set_smaller() {
+        // A ties B, A beats C and C beats B
+        let matrix = matrix(
+            &["A", "B", "C"],
+            &[("A C B", 2), ("B A C", 1), ("C B A", 1)],
+        );
+        assert_eq!(matrix.margin(0, 1), 0);
+        let top_cycle = compute_top_cycle(&matrix);
+        assert_eq!(top_cycle.smith_set, ["A", "B", "C"]);
+        assert_eq!(top_cycle.schwartz_set, ["A"]);
+        assert!(top_cycle.cycles.is_empty());
+    }
+
+    #[test]
+    fn condorcet_winner_is_the_whole_top_cycle() {
+        let matrix = matrix(&["A", "B", "C"], &[("A B C", 3), ("B C A", 2)]);
+        let top_cycle = compute_top_cycle(&matrix);
+        assert_eq!(top_cycle.smith_set, ["A"]);
+        assert_eq!(top_cycle.schwartz_set, ["A"]);
+        assert!(top_cycle.cycles.is_empty());
+    }
+
+    #[test]
+    fn cycles_are_truncated_after_max_cycles() {
+        // every candidate beats the next half of the circle
+        let cands = ["A", "B", "C", "D", "E", "F", "G", "H", "I"];
+        let n_cands = cands.len();
+        let mut counts = vec![0; n_cands * n_cands];
+        for a in 0..n_cands {
+            for step in 1..=n_cands / 2 {
+                counts[a * n_cands + (a + step) % n_cands] = 1;
+            }
+        }
+        let matrix = PairwiseMatrix {
+            cands: cands.to_vec(),
+            counts,
+        };
+        let top_cycle = compute_top_cycle(&matrix);
+        assert_eq!(top_cycle.smith_set, cands);
+        assert_eq!(top_cycle.cycles.len(), MAX_CYCLES);
+        assert!(top_cycle.cycles_truncated);
+    }
 }
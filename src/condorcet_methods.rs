@@ -0,0 +1,610 @@
+use std::cmp::Reverse;
+
+use serde::Serialize;
+
+use crate::{
+    condorcet::{PairwiseMatrix, TieCredit},
+    error::{Error, Result},
+};
+
+/// How the strength of a pairwise defeat is measured
+#[derive(Serialize, Clone, Copy, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Strength {
+    /// Votes for the winner of the pair minus votes for the loser
+    Margin,
+    /// Votes for the winner of the pair
+    WinningVotes,
+}
+
+impl Strength {
+    /// Strength of the defeat of `b` by `a`, or 0 if `a` does not beat `b`
+    pub fn of(self, matrix: &PairwiseMatrix, a: usize, b: usize) -> i64 {
+        if !matrix.wins(a, b) {
+            return 0;
+        }
+        match self {
+            Strength::Margin => matrix.margin(a, b),
+            Strength::WinningVotes => i64::from(matrix.winning_votes(a, b)),
+        }
+    }
+}
+
+/// Candidates from first to last place. Candidates in the same place are tied,
+/// in candidate order. The `winner` next to a ranking is the only candidate in
+/// its first place, or `None` if several are tied there
+pub type Ranking<'a> = Vec<Vec<&'a str>>;
+
+#[derive(Serialize)]
+pub struct SchulzeResult<'a> {
+    pub strength: Strength,
+    pub winner: Option<&'a str>,
+    pub ranking: Ranking<'a>,
+    pub cands: Vec<&'a str>,
+    /// `strongest_paths[a][b]` is the strength of the strongest beatpath from
+    /// candidate `a` to `b`, with the candidates in the order of `cands`
+    pub strongest_paths: Vec<Vec<i64>>,
+}
+
+/// Runs the Schulze (beatpath) method. A candidate is ranked above another if
+/// its strongest beatpath to the other is stronger than the reverse
+pub fn schulze<'a>(matrix: &PairwiseMatrix<'a>, strength: Strength) -> SchulzeResult<'a> {
+    let n_cands = matrix.cands().len();
+
+    let mut paths: Vec<Vec<i64>> = (0..n_cands)
+        .map(|a| (0..n_cands).map(|b| strength.of(matrix, a, b)).collect())
+        .collect();
+    // Floyd-Warshall for the widest path
+    for via in 0..n_cands {
+        for a in (0..n_cands).filter(|a| *a != via) {
+            for b in (0..n_cands).filter(|b| *b != via && *b != a) {
+                let through = paths[a][via].min(paths[via][b]);
+                if through > paths[a][b] {
+                    paths[a][b] = through;
+                }
+            }
+        }
+    }
+
+    // the beatpath relation is transitive, so the number of candidates beaten
+    // orders the candidates consistently with it
+    let n_beaten: Vec<usize> = (0..n_cands)
+        .map(|a| (0..n_cands).filter(|b| paths[a][*b] > paths[*b][a]).count())
+        .collect();
+    let ranking = rank_by_score(matrix.cands(), &n_beaten);
+
+    SchulzeResult {
+        strength,
+        winner: sole_winner(&ranking),
+        ranking,
+        cands: matrix.cands().to_vec(),
+        strongest_paths: paths,
+    }
+}
+
+/// A pairwise victory, in the order it was considered by Ranked Pairs
+#[derive(Serialize)]
+pub struct RankedPair<'a> {
+    pub winner: &'a str,
+    pub loser: &'a str,
+    pub strength: i64,
+    /// Whether the pair was locked in, or skipped as it would create a cycle
+    pub locked: bool,
+}
+
+#[derive(Serialize)]
+pub struct RankedPairsResult<'a> {
+    pub strength: Strength,
+    pub winner: Option<&'a str>,
+    pub ranking: Ranking<'a>,
+    /// Every pairwise victory, strongest first
+    pub pairs: Vec<RankedPair<'a>>,
+}
+
+/// Runs Ranked Pairs (Tideman). Pairwise victories are locked in from the
+/// strongest to the weakest, skipping any that would create a cycle with the
+/// pairs already locked in. Victories of equal strength are taken in order of
+/// fewer votes for the loser, then in candidate order of the winner and loser
+pub fn ranked_pairs<'a>(matrix: &PairwiseMatrix<'a>, strength: Strength) -> RankedPairsResult<'a> {
+    let n_cands = matrix.cands().len();
+
+    let mut victories: Vec<(usize, usize)> = (0..n_cands)
+        .flat_map(|a| (0..n_cands).map(move |b| (a, b)))
+        .filter(|(a, b)| matrix.wins(*a, *b))
+        .collect();
+    victories.sort_by_key(|(a, b)| {
+        (
+            Reverse(strength.of(matrix, *a, *b)),
+            matrix.get(*b, *a),
+            *a,
+            *b,
+        )
+    });
+
+    let mut locked = vec![vec![false; n_cands]; n_cands];
+    let pairs = victories
+        .iter()
+        .map(|(a, b)| {
+            // locking a over b creates a cycle if b already leads to a
+            let is_locked = !reaches(&locked, *b, *a);
+            if is_locked {
+                locked[*a][*b] = true;
+            }
+            RankedPair {
+                winner: matrix.cands()[*a],
+                loser: matrix.cands()[*b],
+                strength: strength.of(matrix, *a, *b),
+                locked: is_locked,
+            }
+        })
+        .collect();
+
+    // the locked graph has no cycles, so a candidate that leads to another
+    // always leads to more candidates than it
+    let n_reached: Vec<usize> = (0..n_cands)
+        .map(|a| {
+            (0..n_cands)
+                .filter(|b| a != *b && reaches(&locked, a, *b))
+                .count()
+        })
+        .collect();
+    let ranking = rank_by_score(matrix.cands(), &n_reached);
+
+    RankedPairsResult {
+        strength,
+        winner: sole_winner(&ranking),
+        ranking,
+        pairs,
+    }
+}
+
+/// Whether `from` leads to `to` through the edges of `graph`
+fn reaches(graph: &[Vec<bool>], from: usize, to: usize) -> bool {
+    let mut visited = vec![false; graph.len()];
+    let mut stack = vec![from];
+    while let Some(node) = stack.pop() {
+        if node == to {
+            return true;
+        }
+        if visited[node] {
+            continue;
+        }
+        visited[node] = true;
+        stack.extend((0..graph.len()).filter(|next| graph[node][*next]));
+    }
+    false
+}
+
+/// What Minimax measures as the worst defeat of a candidate
+#[derive(Serialize, Clone, Copy, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum MinimaxMeasure {
+    /// The largest margin of any candidate over it, which is negative if it
+    /// beats everyone
+    Margin,
+    /// The most votes of any candidate that beats it, 0 if none does
+    WinningVotes,
+    /// The most votes of any candidate over it, whether it won the pair or not
+    PairwiseOpposition,
+}
+
+/// A method that ranks every candidate from the pairwise matrix
+#[derive(Clone, Copy, Debug)]
+pub enum Method {
+    Schulze(Strength),
+    RankedPairs(Strength),
+    /// The candidate whose worst pairwise defeat is the smallest wins
+    Minimax(MinimaxMeasure),
+    /// Ranked by pairwise wins, plus the given credit for pairwise ties
+    Copeland(TieCredit),
+    /// The Condorcet winner if there is one, otherwise the Borda winner
+    Black,
+    /// The order of candidates that agrees with the most pairwise preferences
+    KemenyYoung,
+}
+
+/// Kemeny-Young looks at every subset of the candidates, so is limited to contests
+/// with at most this many candidates
+pub const MAX_KEMENY_CANDS: usize = 16;
+
+impl Method {
+    /// Every variant of every method
+    pub const VARIANTS: [Method; 12] = [
+        Method::Schulze(Strength::WinningVotes),
+        Method::Schulze(Strength::Margin),
+        Method::RankedPairs(Strength::WinningVotes),
+        Method::RankedPairs(Strength::Margin),
+        Method::Minimax(MinimaxMeasure::Margin),
+        Method::Minimax(MinimaxMeasure::WinningVotes),
+        Method::Minimax(MinimaxMeasure::PairwiseOpposition),
+        Method::Copeland(TieCredit::Zero),
+        Method::Copeland(TieCredit::Half),
+        Method::Copeland(TieCredit::One),
+        Method::Black,
+        Method::KemenyYoung,
+    ];
+
+    /// The variants compared by `nyc-irv analyze` when no method is given, which
+    /// leaves out the Copeland variants with unusual tie credits
+    pub const ALL: [Method; 10] = [
+        Method::Schulze(Strength::WinningVotes),
+        Method::Schulze(Strength::Margin),
+        Method::RankedPairs(Strength::WinningVotes),
+        Method::RankedPairs(Strength::Margin),
+        Method::Minimax(MinimaxMeasure::Margin),
+        Method::Minimax(MinimaxMeasure::WinningVotes),
+        Method::Minimax(MinimaxMeasure::PairwiseOpposition),
+        Method::Copeland(TieCredit::Half),
+        Method::Black,
+        Method::KemenyYoung,
+    ];
+
+    /// Schulze and Ranked Pairs measure defeats by winning votes unless the
+    /// name ends in `_margin`
+    pub fn name(self) -> &'static str {
+        match self {
+            Method::Schulze(Strength::Margin) => "schulze_margin",
+            Method::Schulze(Strength::WinningVotes) => "schulze",
+            Method::RankedPairs(Strength::Margin) => "ranked_pairs_margin",
+            Method::RankedPairs(Strength::WinningVotes) => "ranked_pairs",
+            Method::Minimax(MinimaxMeasure::Margin) => "minimax_margin",
+            Method::Minimax(MinimaxMeasure::WinningVotes) => "minimax_winning_votes",
+            Method::Minimax(MinimaxMeasure::PairwiseOpposition) => "minimax_opposition",
+            Method::Copeland(TieCredit::Zero) => "copeland_zero",
+            Method::Copeland(TieCredit::Half) => "copeland",
+            Method::Copeland(TieCredit::One) => "copeland_one",
+            Method::Black => "black",
+            Method::KemenyYoung => "kemeny_young",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Result<Self> {
+        Method::VARIANTS
+            .into_iter()
+            .find(|method| method.name() == name)
+            .ok_or_else(|| Error::UnknownName {
+                kind: "method",
+                name: name.to_owned(),
+                expected: Method::VARIANTS.map(Method::name).join(", "),
+            })
+    }
+}
+
+#[derive(Serialize)]
+pub struct MethodRanking<'a> {
+    pub method: &'static str,
+    pub winner: Option<&'a str>,
+    pub ranking: Ranking<'a>,
+}
+
+/// Ranks the candidates with the given method
+pub fn rank_candidates<'a>(
+    matrix: &PairwiseMatrix<'a>,
+    method: Method,
+) -> Result<MethodRanking<'a>> {
+    let ranking = match method {
+        Method::Schulze(strength) => schulze(matrix, strength).ranking,
+        Method::RankedPairs(strength) => ranked_pairs(matrix, strength).ranking,
+        Method::Minimax(measure) => minimax(matrix, measure),
+        Method::Copeland(tie_credit) => copeland(matrix, tie_credit),
+        Method::Black => black(matrix),
+        Method::KemenyYoung => kemeny_young(matrix)?,
+    };
+    Ok(MethodRanking {
+        method: method.name(),
+        winner: sole_winner(&ranking),
+        ranking,
+    })
+}
+
+pub fn minimax<'a>(matrix: &PairwiseMatrix<'a>, measure: MinimaxMeasure) -> Ranking<'a> {
+    let n_cands = matrix.cands().len();
+    let worst_defeats: Vec<i64> = (0..n_cands)
+        .map(|a| {
+            (0..n_cands)
+                .filter(|b| *b != a)
+                .map(|b| match measure {
+                    MinimaxMeasure::Margin => matrix.margin(b, a),
+                    MinimaxMeasure::WinningVotes => i64::from(matrix.winning_votes(b, a)),
+                    MinimaxMeasure::PairwiseOpposition => i64::from(matrix.get(b, a)),
+                })
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+    // the smallest worst defeat is first
+    let scores: Vec<i64> = worst_defeats.iter().map(|defeat| -defeat).collect();
+    rank_by_score(matrix.cands(), &scores)
+}
+
+pub fn copeland<'a>(matrix: &PairwiseMatrix<'a>, tie_credit: TieCredit) -> Ranking<'a> {
+    let n_cands = matrix.cands().len();
+    let scores: Vec<f64> = (0..n_cands)
+        .map(|a| {
+            (0..n_cands)
+                .filter(|b| *b != a)
+                .map(|b| match matrix.margin(a, b) {
+                    m if m > 0 => 1.,
+                    0 => tie_credit.points(),
+                    _ => 0.,
+                })
+                .sum()
+        })
+        .collect();
+    rank_by_score(matrix.cands(), &scores)
+}
+
+/// The Condorcet winner first if there is one, then every other candidate by
+/// Borda score. The Borda score is the number of candidates each voter ranked
+/// the candidate above, so unranked candidates are tied at the bottom
+pub fn black<'a>(matrix: &PairwiseMatrix<'a>) -> Ranking<'a> {
+    let n_cands = matrix.cands().len();
+    let mut scores: Vec<u64> = (0..n_cands)
+        .map(|a| (0..n_cands).map(|b| u64::from(matrix.get(a, b))).sum())
+        .collect();
+    let condorcet_winner =
+        (0..n_cands).find(|a| (0..n_cands).all(|b| *a == b || matrix.wins(*a, b)));
+    if let Some(winner) = condorcet_winner {
+        scores[winner] = u64::MAX;
+    }
+    rank_by_score(matrix.cands(), &scores)
+}
+
+/// Finds the order of the candidates that agrees with the most voter
+/// preferences between pairs. Of equally good orders, the one with the latest
+/// candidate in candidate order in last place is kept, then likewise for each
+/// place above it, so the same matrix always gives the same order
+pub fn kemeny_young<'a>(matrix: &PairwiseMatrix<'a>) -> Result<Ranking<'a>> {
+    let n_cands = matrix.cands().len();
+    if n_cands > MAX_KEMENY_CANDS {
+        return Err(Error::TooManyCandidates {
+            method: "Kemeny-Young",
+            max: MAX_KEMENY_CANDS,
+            n_cands,
+        });
+    }
+
+    let row_totals: Vec<i64> = (0..n_cands)
+        .map(|a| (0..n_cands).map(|b| i64::from(matrix.get(a, b))).sum())
+        .collect();
+
+    // best[placed] is the most agreement of any order of the candidates in the
+    // bitset `placed` at the top, and last[placed] is the candidate placed last
+    let n_sets = 1 << n_cands;
+    let mut best = vec![i64::MIN; n_sets];
+    let mut last = vec![0; n_sets];
+    best[0] = 0;
+    for placed in 0..n_sets {
+        if best[placed] == i64::MIN {
+            continue;
+        }
+        for next in (0..n_cands).filter(|c| placed & (1 << c) == 0) {
+            // `next` is placed above every candidate that hasn't been placed yet
+            let above_placed: i64 = (0..n_cands)
+                .filter(|c| placed & (1 << c) != 0)
+                .map(|c| i64::from(matrix.get(next, c)))
+                .sum();
+            let agreement = best[placed] + row_totals[next] - above_placed;
+            let with_next = placed | (1 << next);
+            if agreement > best[with_next] {
+                best[with_next] = agreement;
+                last[with_next] = next;
+            }
+        }
+    }
+
+    let mut order = vec![];
+    let mut placed = n_sets - 1;
+    while placed != 0 {
+        order.push(last[placed]);
+        placed &= !(1 << last[placed]);
+    }
+    order.reverse();
+    Ok(order.iter().map(|idx| vec![matrix.cands()[*idx]]).collect())
+}
+
+/// Groups the candidates into places, highest score first
+fn rank_by_score<'a, T: PartialOrd>(cands: &[&'a str], scores: &[T]) -> Ranking<'a> {
+    let mut order: Vec<usize> = (0..cands.len()).collect();
+    // stable sort, so tied candidates are kept in candidate order
+    order.sort_by(|a, b| scores[*b].partial_cmp(&scores[*a]).unwrap());
+
+    let mut ranking: Ranking = vec![];
+    for (pos, idx) in order.iter().enumerate() {
+        if pos > 0 && scores[order[pos - 1]] == scores[*idx] {
+            ranking.last_mut().unwrap().push(cands[*idx]);
+        } else {
+            ranking.push(vec![cands[*idx]]);
+        }
+    }
+    ranking
+}
+
+fn sole_winner<'a>(ranking: &[Vec<&'a str>]) -> Option<&'a str> {
+    match ranking.first().map(|place| place.as_slice()) {
+        Some([winner]) => Some(winner),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        condorcet::compute_pairwise_matrix,
+        core::{Choice, WeightedBallot},
+    };
+
+    fn matrix(cands: &[&'static str], ballots: &[(&'static str, u32)]) -> PairwiseMatrix<'static> {
+        let ballots: Vec<WeightedBallot> = ballots
+            .iter()
+            .map(|(ranks, count)| WeightedBallot {
+                choices: ranks.split(' ').map(Choice::Candidate).collect(),
+                count: *count,
+            })
+            .collect();
+        compute_pairwise_matrix(cands, &ballots)
+    }
+
+    /// The 45 voters of the Schulze method example on Wikipedia
+    fn wikipedia() -> PairwiseMatrix<'static> {
+        matrix(
+            &["A", "B", "C", "D", "E"],
+            &[
+                ("A C B E D", 5),
+                ("A D E C B", 5),
+                ("B E D A C", 8),
+                ("C A B E D", 3),
+                ("C A E B D", 7),
+                ("C B A D E", 2),
+                ("D C E B A", 7),
+                ("E B A D C", 8),
+            ],
+        )
+    }
+
+    #[test]
+    fn schulze_wikipedia_example() {
+        let matrix = wikipedia();
+        for strength in [Strength::WinningVotes, Strength::Margin] {
+            let result = schulze(&matrix, strength);
+            assert_eq!(result.winner, Some("E"));
+            assert_eq!(result.ranking, [["E"], ["A"], ["C"], ["B"], ["D"]]);
+        }
+        // the strongest paths given for winning votes
+        let paths = schulze(&matrix, Strength::WinningVotes).strongest_paths;
+        assert_eq!(paths[0], [0, 28, 28, 30, 24]);
+        assert_eq!(paths[4], [25, 28, 28, 31, 0]);
+    }
+
+    #[test]
+    fn ranked_pairs_wikipedia_example() {
+        let matrix = wikipedia();
+        for strength in [Strength::Margin, Strength::WinningVotes] {
+            let result = ranked_pairs(&matrix, strength);
+            assert_eq!(result.winner, Some("A"));
+            assert_eq!(result.ranking, [["A"], ["C"], ["E"], ["B"], ["D"]]);
+            let skipped: Vec<_> = result
+                .pairs
+                .iter()
+                .filter(|pair| !pair.locked)
+                .map(|pair| (pair.winner, pair.loser))
+                .collect();
+            assert_eq!(skipped, [("D", "C"), ("B", "A"), ("E", "A")]);
+        }
+    }
+
+    #[test]
+    fn ranked_pairs_equal_strengths_take_fewer_loser_votes_then_candidate_order() {
+        // every margin is 1: A beats B 2-1, B beats C 3-2 and C beats A 3-2
+        let matrix = matrix(&["C", "B", "A"], &[("A B C", 2), ("B C A", 1), ("C", 2)]);
+        let result = ranked_pairs(&matrix, Strength::Margin);
+        let order: Vec<_> = result
+            .pairs
+            .iter()
+            .map(|pair| (pair.winner, pair.loser, pair.locked))
+            .collect();
+        assert_eq!(
+            order,
+            [("A", "B", true), ("C", "A", true), ("B", "C", false)]
+        );
+        assert_eq!(result.ranking, [["C"], ["A"], ["B"]]);
+    }
+
+    #[test]
+    fn minimax_measures() {
+        // B beats A by 6-3, A beats C by 5-4 and C beats B by 4-2
+        let matrix = matrix(&["A", "B", "C"], &[("A", 3), ("B A C", 2), ("C B A", 4)]);
+        assert_eq!(
+            minimax(&matrix, MinimaxMeasure::Margin),
+            [["C"], ["B"], ["A"]]
+        );
+        for measure in [
+            MinimaxMeasure::WinningVotes,
+            MinimaxMeasure::PairwiseOpposition,
+        ] {
+            assert_eq!(minimax(&matrix, measure), [["B"], ["C"], ["A"]]);
+        }
+    }
+
+    #[test]
+    fn copeland_tie_credits() {
+        // A ties B, A beats C and C beats B
+        let matrix = matrix(
+            &["A", "B", "C"],
+            &[("A C B", 2), ("B A C", 1), ("C B A", 1)],
+        );
+        assert_eq!(
+            copeland(&matrix, TieCredit::Zero),
+            [vec!["A", "C"], vec!["B"]]
+        );
+        assert_eq!(copeland(&matrix, TieCredit::Half), [["A"], ["C"], ["B"]]);
+        assert_eq!(
+            copeland(&matrix, TieCredit::One),
+            [vec!["A"], vec!["B", "C"]]
+        );
+    }
+
+    #[test]
+    fn black_puts_the_condorcet_winner_above_the_borda_order() {
+        // A is the Condorcet winner, but B has the highest Borda score
+        let matrix = matrix(&["A", "B", "C"], &[("A B C", 3), ("B C A", 2)]);
+        assert_eq!(black(&matrix), [["A"], ["B"], ["C"]]);
+        // without a Condorcet winner it is the Borda order
+        assert_eq!(black(&wikipedia()), [["E"], ["A"], ["B"], ["C"], ["D"]]);
+    }
+
+    #[test]
+    fn kemeny_young_tennessee() {
+        let matrix = matrix(
+            &["Memphis", "Nashville", "Chattanooga", "Knoxville"],
+            &[
+                ("Memphis Nashville Chattanooga Knoxville", 42),
+                ("Nashville Chattanooga Knoxville Memphis", 26),
+                ("Chattanooga Knoxville Nashville Memphis", 15),
+                ("Knoxville Chattanooga Nashville Memphis", 17),
+            ],
+        );
+        assert_eq!(
+            kemeny_young(&matrix).unwrap(),
+            [["Nashville"], ["Chattanooga"], ["Knoxville"], ["Memphis"]]
+        );
+    }
+
+    #[test]
+    fn kemeny_young_keeps_the_latest_candidate_last_among_equal_orders() {
+        // A > B > C, B > C > A and C > A > B all agree with 5 preferences
+        let ballots = [("A B C", 1), ("B C A", 1), ("C A B", 1)];
+        let ranking = kemeny_young(&matrix(&["A", "B", "C"], &ballots)).unwrap();
+        assert_eq!(ranking, [["A"], ["B"], ["C"]]);
+        let ranking = kemeny_young(&matrix(&["C", "B", "A"], &ballots)).unwrap();
+        assert_eq!(ranking, [["B"], ["C"], ["A"]]);
+    }
+
+    #[test]
+    fn kemeny_young_rejects_too_many_candidates() {
+        let cands = [
+            "A", "B", "C", "D", "E", "F", "G", "H", "I", "J", "K", "L", "M", "N", "O", "P", "Q",
+        ];
+        let result = kemeny_young(&matrix(&cands, &[("A", 1)]));
+        assert!(matches!(
+            result,
+            Err(Error::TooManyCandidates {
+                max: MAX_KEMENY_CANDS,
+                n_cands: 17,
+                ..
+            })
+        ));
+        assert!(kemeny_young(&matrix(&cands[..MAX_KEMENY_CANDS], &[("A", 1)])).is_ok());
+    }
+
+    #[test]
+    fn every_variant_has_its_own_name() {
+        let names = Method::VARIANTS.map(Method::name);
+        for (idx, name) in names.iter().enumerate() {
+            assert!(!names[idx + 1..].contains(name), "{name} is repeated");
+            assert_eq!(Method::from_name(name).unwrap().name(), *name);
+        }
+        assert!(Method::from_name("ranked_pairs_winning_votes").is_err());
+    }
+}
@@ -0,0 +1,224 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::election::HeaderFormat;
+
+/// A ranked contest found in the header row of a CVR workbook
+#[derive(Clone, Debug)]
+pub struct Contest {
+    /// The header without the "Choice N of M" part and the ID,
+    /// eg "DEM Mayor Citywide"
+    pub name: String,
+    /// The ID in brackets at the end of the header of the first choice, eg "026916"
+    pub id: String,
+    /// Column index of every choice, in order of rank
+    pub columns: Vec<usize>,
+}
+
+pub enum ContestSelector {
+    /// Every ranked contest
+    All,
+    Id(String),
+    /// Case insensitive substring of the contest name
+    Pattern(String),
+}
+
+impl ContestSelector {
+    /// A selector made only of digits is an ID, anything else is a name pattern
+    pub fn parse(s: &str) -> Self {
+        if !s.is_empty() && s.chars().all(|c| c.is_ascii_digit()) {
+            ContestSelector::Id(s.to_owned())
+        } else {
+            ContestSelector::Pattern(s.to_lowercase())
+        }
+    }
+
+    pub fn matches(&self, contest: &Contest) -> bool {
+        match self {
+            ContestSelector::All => true,
+            ContestSelector::Id(id) => contest.id == *id,
+            ContestSelector::Pattern(pattern) => contest.name.to_lowercase().contains(pattern),
+        }
+    }
+}
+
+/// An entry of `contests.json`, the index of the contests written by `parse`
+#[derive(Serialize)]
+pub struct ContestIndexEntry {
+    pub id: String,
+    pub name: String,
+    pub ranks: usize,
+    pub n_ballots: usize,
+    pub n_candidates: usize,
+    /// The directory containing the `ballots.bin` of this contest
+    pub dir: String,
+}
+
+struct ChoiceHeader<'a> {
+    prefix: &'a str,
+    choice: usize,
+    n_choices: usize,
+    suffix: &'a str,
+    id: &'a str,
+}
+
+/// Parses a header like "DEM Mayor Choice 1 of 5 Citywide (026916)"
+fn parse_choice_header<'a>(header: &'a str, format: &HeaderFormat) -> Option<ChoiceHeader<'a>> {
+    let (prefix, rest) = header.split_once(&format!(" {} ", format.choice))?;
+    let (choice, rest) = rest.split_once(&format!(" {} ", format.of))?;
+    let (n_choices, rest) = rest.split_once(' ').unwrap_or((rest, ""));
+    // the ID is in brackets at the very end
+    let (suffix, id) = rest.strip_suffix(')')?.rsplit_once('(')?;
+    Some(ChoiceHeader {
+        prefix,
+        choice: choice.parse().ok()?,
+        n_choices: n_choices.parse().ok()?,
+        suffix: suffix.trim_end(),
+        id,
+    })
+}
+
+/// Finds every ranked contest in a header row, in order of their first column.
+/// Contests that don't have a column for every choice are ignored
+pub fn find_contests(header: &[String], format: &HeaderFormat) -> Vec<Contest> {
+    // the columns of a contest share everything but the choice number (and maybe the ID)
+    let mut groups = HashMap::new();
+    for (col, cell) in header.iter().enumerate() {
+        let Some(h) = parse_choice_header(cell, format) else {
+            continue;
+        };
+        if h.choice == 0 || h.choice > h.n_choices {
+            continue;
+        }
+        let columns = groups
+            .entry((h.prefix, h.suffix, h.n_choices))
+            .or_insert_with(|| vec![None; h.n_choices]);
+        columns[h.choice - 1].get_or_insert((col, h.id));
+    }
+
+    let mut contests: Vec<Contest> = groups
+        .into_iter()
+        .filter_map(|((prefix, suffix, _), columns)| {
+            let columns: Option<Vec<_>> = columns.into_iter().collect();
+            let columns = columns?;
+            let name = if suffix.is_empty() {
+                prefix.to_owned()
+            } else {
+                format!("{prefix} {suffix}")
+            };
+            Some(Contest {
+                name,
+                id: columns[0].1.to_owned(),
+                columns: columns.iter().map(|(col, _)| *col).collect(),
+            })
+        })
+        .collect();
+    contests.sort_by_key(|contest| contest.columns[0]);
+    contests
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(cells: &[&str]) -> Vec<String> {
+        cells.iter().map(|cell| cell.to_string()).collect()
+    }
+
+    #[test]
+    fn parses_headers_with_and_without_suffix() {
+        let format = HeaderFormat::default();
+        let h = parse_choice_header("DEM Mayor Choice 2 of 5 Citywide (026916)", &format).unwrap();
+        assert_eq!(
+            (h.prefix, h.choice, h.n_choices, h.suffix, h.id),
+            ("DEM Mayor", 2, 5, "Citywide", "026916")
+        );
+        let h = parse_choice_header("REP Council Choice 1 of 3 (123)", &format).unwrap();
+        assert_eq!(
+            (h.prefix, h.choice, h.n_choices, h.suffix, h.id),
+            ("REP Council", 1, 3, "", "123")
+        );
+        assert!(parse_choice_header("Cast Vote Record", &format).is_none());
+        assert!(parse_choice_header("DEM Mayor Choice 1 of 5 Citywide", &format).is_none());
+        assert!(parse_choice_header("DEM Mayor Choice one of 5 (1)", &format).is_none());
+    }
+
+    #[test]
+    fn groups_columns_whose_ids_differ_per_choice() {
+        // NYC gives every choice column its own ID, the contest takes the first
+        let contests = find_contests(
+            &header(&[
+                "Cast Vote Record",
+                "DEM Mayor Choice 1 of 3 Citywide (026916)",
+                "DEM Mayor Choice 2 of 3 Citywide (026917)",
+                "DEM Mayor Choice 3 of 3 Citywide (026918)",
+                "DEM Council Choice 1 of 2 District 1 (027001)",
+                "DEM Council Choice 2 of 2 District 1 (027002)",
+            ]),
+            &HeaderFormat::default(),
+        );
+        let found: Vec<_> = contests
+            .iter()
+            .map(|c| (c.name.as_str(), c.id.as_str(), c.columns.clone()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("DEM Mayor Citywide", "026916", vec![1, 2, 3]),
+                ("DEM Council District 1", "027001", vec![4, 5]),
+            ]
+        );
+    }
+
+    #[test]
+    fn orders_columns_by_choice_and_drops_incomplete_contests() {
+        let contests = find_contests(
+            &header(&[
+                "DEM Council Choice 1 of 3 District 2 (2)",
+                "DEM Mayor Choice 2 of 2 (1)",
+                "DEM Council Choice 3 of 3 District 2 (2)",
+                "DEM Mayor Choice 1 of 2 (1)",
+                // neither has a choice 2, and there is no choice 0 or 3 of 2
+                "DEM Comptroller Choice 0 of 2 (3)",
+                "DEM Comptroller Choice 3 of 2 (3)",
+                "DEM Comptroller Choice 1 of 2 (3)",
+            ]),
+            &HeaderFormat::default(),
+        );
+        assert_eq!(contests.len(), 1);
+        assert_eq!(contests[0].name, "DEM Mayor");
+        assert_eq!(contests[0].columns, [3, 1]);
+    }
+
+    #[test]
+    fn selector_tells_an_id_from_a_pattern() {
+        let contest = Contest {
+            name: "DEM Mayor Citywide".to_owned(),
+            id: "026916".to_owned(),
+            columns: vec![0],
+        };
+        assert!(matches!(
+            ContestSelector::parse("026916"),
+            ContestSelector::Id(_)
+        ));
+        assert!(ContestSelector::parse("026916").matches(&contest));
+        assert!(!ContestSelector::parse("26916").matches(&contest));
+        assert!(matches!(
+            ContestSelector::parse("Mayor"),
+            ContestSelector::Pattern(_)
+        ));
+        assert!(ContestSelector::parse("mAYOR cITY").matches(&contest));
+        assert!(!ContestSelector::parse("Council").matches(&contest));
+        // digits with anything else are a pattern
+        assert!(matches!(
+            ContestSelector::parse("D1"),
+            ContestSelector::Pattern(_)
+        ));
+        assert!(matches!(
+            ContestSelector::parse(""),
+            ContestSelector::Pattern(_)
+        ));
+        assert!(ContestSelector::All.matches(&contest));
+    }
+}
@@ -2,6 +2,8 @@ use std::{collections::HashMap, fs::File, path::Path};
 
 use serde::Serialize;
 
+use crate::error::{Error, Result};
+
 #[derive(Serialize, Clone)]
 pub struct Node {
     pub name: String,
@@ -18,10 +20,122 @@ pub enum NodeInternal {
     Children(HashMap<String, NodeInternal>),
 }
 
-pub fn writeable_file<P: AsRef<Path>>(path: P) -> Result<File, std::io::Error> {
+pub fn writeable_file<P: AsRef<Path>>(path: P) -> Result<File> {
+    let path = path.as_ref();
     File::options()
         .write(true)
         .create(true)
         .truncate(true)
         .open(path)
+        .map_err(|source| Error::File {
+            path: path.to_owned(),
+            source,
+        })
+}
+
+/// Writes `value` as compact JSON to a new file at `path`
+pub fn write_json<P: AsRef<Path>, T: Serialize + ?Sized>(path: P, value: &T) -> Result<()> {
+    let path = path.as_ref();
+    serde_json::to_writer(writeable_file(path)?, value).map_err(|source| Error::Json {
+        path: path.to_owned(),
+        source,
+    })
+}
+
+/// Writes `value` as indented JSON, for files that are read by people
+pub fn write_json_pretty<P: AsRef<Path>, T: Serialize + ?Sized>(path: P, value: &T) -> Result<()> {
+    let path = path.as_ref();
+    serde_json::to_writer_pretty(writeable_file(path)?, value).map_err(|source| Error::Json {
+        path: path.to_owned(),
+        source,
+    })
+}
+
+/// A single ranking on a ballot
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+pub enum Choice<'a> {
+    Candidate(&'a str),
+    /// More than one candidate was marked at this rank
+    Overvote,
+    /// Nothing was marked at this rank, or the mark could not be used
+    Undervote,
+    WriteIn,
+}
+
+impl<'a> Choice<'a> {
+    pub fn candidate(self) -> Option<&'a str> {
+        match self {
+            Choice::Candidate(cand) => Some(cand),
+            Choice::Overvote | Choice::Undervote | Choice::WriteIn => None,
+        }
+    }
+}
+
+/// A distinct ranking, and the number of voters that cast it
+#[derive(Clone, Debug)]
+pub struct WeightedBallot<'a> {
+    pub choices: Vec<Choice<'a>>,
+    pub count: u32,
+}
+
+impl<'a> WeightedBallot<'a> {
+    /// The ranked candidates in order, without the overvotes, undervotes and write-ins
+    pub fn ranked(&self) -> impl Iterator<Item = &'a str> + '_ {
+        self.choices.iter().filter_map(|choice| choice.candidate())
+    }
+}
+
+/// Collapses identical ballots into one weighted ballot each, most common first
+pub fn count_unique_ballots<'a>(all_ballots: Vec<Vec<Choice<'a>>>) -> Vec<WeightedBallot<'a>> {
+    let mut counts: HashMap<Vec<Choice>, u32> = HashMap::new();
+    for ballot in all_ballots {
+        *counts.entry(ballot).or_insert(0) += 1;
+    }
+    let mut weighted: Vec<_> = counts
+        .into_iter()
+        .map(|(choices, count)| WeightedBallot { choices, count })
+        .collect();
+    // sort by the choices as well, so the order doesn't depend on the hashmap
+    weighted.sort_unstable_by(|a, b| {
+        b.count
+            .cmp(&a.count)
+            .then_with(|| a.choices.cmp(&b.choices))
+    });
+    weighted
+}
+
+/// Small deterministic PRNG (SplitMix64), so that anything drawn from a seed
+/// is reproducible across platforms and dependency versions
+pub struct SplitMix64(u64);
+
+impl SplitMix64 {
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniformly draws a number in `[0, 1)`
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1_u64 << 53) as f64
+    }
+
+    /// Uniformly draws a number in `0..n`
+    pub fn below(&mut self, n: usize) -> usize {
+        // rejection sampling to avoid modulo bias
+        let n = n as u64;
+        let zone = u64::MAX - u64::MAX % n;
+        loop {
+            let x = self.next_u64();
+            if x < zone {
+                return (x % n) as usize;
+            }
+        }
+    }
 }
@@ -0,0 +1,209 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use calamine::{Data, Range, Reader, Xlsx, open_workbook};
+use serde::Serialize;
+
+use crate::{
+    contest::{Contest, ContestSelector, find_contests},
+    core::Choice,
+    data_quality::QualityCounts,
+    election::ElectionConfig,
+    error::{Error, Result},
+};
+
+/// A cell that could not be read as a choice, and was counted as an undervote
+#[derive(Serialize, Clone, Debug)]
+pub struct Anomaly {
+    pub path: PathBuf,
+    pub sheet: String,
+    /// 1-based, as shown in a spreadsheet program
+    pub row: usize,
+    /// 1-based, as shown in a spreadsheet program
+    pub column: usize,
+    /// ID of the contest the cell belongs to
+    pub contest: String,
+    #[serde(flatten)]
+    pub kind: AnomalyKind,
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "snake_case", tag = "kind", content = "value")]
+pub enum AnomalyKind {
+    /// Text that is neither a marker nor a candidate ID
+    UnknownCandidate(String),
+    /// A number, date or error where text was expected
+    NonStringCell(String),
+}
+
+/// The ballots of the selected contests in one CVR workbook
+pub struct WorkbookBallots<'a> {
+    pub path: PathBuf,
+    pub contests: Vec<ContestBallots<'a>>,
+    pub anomalies: Vec<Anomaly>,
+}
+
+pub struct ContestBallots<'a> {
+    pub contest: Contest,
+    pub ballots: Vec<Vec<Choice<'a>>>,
+    pub quality: QualityCounts,
+}
+
+/// Candidate ID -> candidate name, from the candidate workbook. The first row
+/// is a header
+pub fn read_candidates(config: &ElectionConfig) -> Result<HashMap<String, String>> {
+    let path = &config.candidates;
+    let range = read_sheet(path, &config.candidates_sheet)?;
+    let (first_row, first_col) = range.start().unwrap_or((0, 0));
+
+    let mut candidates = HashMap::new();
+    for (row_idx, row) in range.rows().enumerate().skip(1) {
+        let mut cells = row
+            .iter()
+            .enumerate()
+            .take(2)
+            .map(|(col_idx, cell)| match cell {
+                Data::Empty | Data::Error(_) => Err(Error::Cell {
+                    path: path.clone(),
+                    sheet: config.candidates_sheet.clone(),
+                    row: first_row as usize + row_idx + 1,
+                    column: first_col as usize + col_idx + 1,
+                    message: "Expected a candidate ID and name".to_owned(),
+                }),
+                _ => Ok(cell.to_string()),
+            });
+        let (Some(id), Some(name)) = (cells.next(), cells.next()) else {
+            return Err(Error::Cell {
+                path: path.clone(),
+                sheet: config.candidates_sheet.clone(),
+                row: first_row as usize + row_idx + 1,
+                column: first_col as usize + 1,
+                message: "Expected a candidate ID and name".to_owned(),
+            });
+        };
+        candidates.insert(id?, name?);
+    }
+    Ok(candidates)
+}
+
+/// Every ranked contest in the header row of a CVR workbook
+pub fn read_contests(path: &Path, config: &ElectionConfig) -> Result<Vec<Contest>> {
+    let range = read_sheet(path, &config.sheet)?;
+    Ok(find_contests(&read_header(&range), &config.header))
+}
+
+/// Reads the ballots of every contest matching one of the selectors, and with
+/// `config.ranks` ranks if set. Voters whose cells of a contest are all empty
+/// didn't have the contest on their ballot, and are skipped. The data quality
+/// of each contest is counted as its cells are read
+pub fn read_ballots<'a>(
+    path: &Path,
+    config: &ElectionConfig,
+    selectors: &[ContestSelector],
+    candidates: &'a HashMap<String, String>,
+) -> Result<WorkbookBallots<'a>> {
+    let range = read_sheet(path, &config.sheet)?;
+    let (first_row, first_col) = range.start().unwrap_or((0, 0));
+
+    let contests: Vec<Contest> = find_contests(&read_header(&range), &config.header)
+        .into_iter()
+        .filter(|contest| selectors.iter().any(|s| s.matches(contest)))
+        .filter(|contest| {
+            config
+                .ranks
+                .is_none_or(|ranks| contest.columns.len() == ranks)
+        })
+        .collect();
+
+    let mut anomalies = vec![];
+    let mut all_ballots: Vec<ContestBallots> = contests
+        .into_iter()
+        .map(|contest| ContestBallots {
+            quality: QualityCounts::new(contest.columns.len()),
+            contest,
+            ballots: vec![],
+        })
+        .collect();
+    for (row_idx, row) in range.rows().enumerate().skip(1) {
+        for ContestBallots {
+            contest,
+            ballots,
+            quality,
+        } in all_ballots.iter_mut()
+        {
+            // voters in other districts don't have this contest on their ballot,
+            // so all their cells for this contest are empty. Any other cell is
+            // a mark, even one that can't be read
+            if contest
+                .columns
+                .iter()
+                .all(|col| matches!(row[*col], Data::Empty))
+            {
+                continue;
+            }
+
+            let first_anomaly = anomalies.len();
+            let mut anomaly = |col: usize, kind| {
+                anomalies.push(Anomaly {
+                    path: path.to_owned(),
+                    sheet: config.sheet.clone(),
+                    row: first_row as usize + row_idx + 1,
+                    column: first_col as usize + col + 1,
+                    contest: contest.id.clone(),
+                    kind,
+                })
+            };
+
+            let this_voters_ballot: Vec<Choice> = contest
+                .columns
+                .iter()
+                .map(|col| match &row[*col] {
+                    // overvotes are kept apart from undervotes, as the
+                    // tabulation rules can exhaust ballots at an overvote
+                    Data::String(value) => match config.markers.choice(value) {
+                        Some(choice) => choice,
+                        None => match candidates.get(value) {
+                            Some(x) => Choice::Candidate(x.as_ref()),
+                            None => {
+                                anomaly(*col, AnomalyKind::UnknownCandidate(value.clone()));
+                                Choice::Undervote
+                            }
+                        },
+                    },
+                    Data::Empty => Choice::Undervote,
+                    cell => {
+                        anomaly(*col, AnomalyKind::NonStringCell(cell.to_string()));
+                        Choice::Undervote
+                    }
+                })
+                .collect();
+            quality.add_ballot(&this_voters_ballot, &anomalies[first_anomaly..]);
+            ballots.push(this_voters_ballot);
+        }
+    }
+
+    Ok(WorkbookBallots {
+        path: path.to_owned(),
+        contests: all_ballots,
+        anomalies,
+    })
+}
+
+fn read_sheet(path: &Path, sheet: &str) -> Result<Range<Data>> {
+    let workbook_error = |source| Error::Workbook {
+        path: path.to_owned(),
+        source,
+    };
+    let mut workbook: Xlsx<_> = open_workbook(path).map_err(workbook_error)?;
+    workbook.worksheet_range(sheet).map_err(workbook_error)
+}
+
+fn read_header(range: &Range<Data>) -> Vec<String> {
+    range
+        .rows()
+        .next()
+        .map(|header| header.iter().map(|x| x.to_string()).collect())
+        .unwrap_or_default()
+}
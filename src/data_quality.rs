@@ -0,0 +1,277 @@
+use std::{fmt, path::PathBuf};
+
+use serde::Serialize;
+
+use crate::{
+    core::Choice,
+    cvr::{Anomaly, AnomalyKind},
+};
+
+/// How a contest's ballots were marked, before duplicates are dropped and
+/// unreadable cells are counted as undervotes
+#[derive(Serialize, Clone, Debug)]
+pub struct QualityCounts {
+    pub ballots: u32,
+    /// Ballots with the contest on them but nothing marked in it
+    pub blank_ballots: u32,
+    /// Index 0 is the first choice
+    pub overvotes_by_rank: Vec<u32>,
+    /// Empty cells and undervote markers
+    pub undervotes: u32,
+    /// Undervoted or unreadable ranks followed by a later choice
+    pub skipped_ranks: u32,
+    /// Rankings of a candidate already ranked higher on the same ballot
+    pub duplicate_rankings: u32,
+    pub write_ins: u32,
+    pub unknown_candidates: u32,
+    pub non_string_cells: u32,
+}
+
+impl QualityCounts {
+    pub fn new(ranks: usize) -> Self {
+        Self {
+            ballots: 0,
+            blank_ballots: 0,
+            overvotes_by_rank: vec![0; ranks],
+            undervotes: 0,
+            skipped_ranks: 0,
+            duplicate_rankings: 0,
+            write_ins: 0,
+            unknown_candidates: 0,
+            non_string_cells: 0,
+        }
+    }
+
+    /// Counts one voter's ballot. `anomalies` are the cells of this ballot
+    /// that could not be read, which are undervotes in `ballot`
+    pub fn add_ballot(&mut self, ballot: &[Choice], anomalies: &[Anomaly]) {
+        self.ballots += 1;
+
+        let mut ranked = vec![];
+        let mut undervotes = 0;
+        for (rank, choice) in ballot.iter().enumerate() {
+            match choice {
+                Choice::Candidate(cand) => {
+                    if ranked.contains(cand) {
+                        self.duplicate_rankings += 1;
+                    } else {
+                        ranked.push(*cand);
+                    }
+                }
+                Choice::Overvote => self.overvotes_by_rank[rank] += 1,
+                Choice::Undervote => undervotes += 1,
+                Choice::WriteIn => self.write_ins += 1,
+            }
+        }
+
+        // unreadable cells were read as undervotes, but aren't blank
+        debug_assert!(
+            anomalies.len() <= undervotes,
+            "anomalies must be undervotes"
+        );
+        self.undervotes += undervotes.saturating_sub(anomalies.len()) as u32;
+        for anomaly in anomalies {
+            match anomaly.kind {
+                AnomalyKind::UnknownCandidate(_) => self.unknown_candidates += 1,
+                AnomalyKind::NonStringCell(_) => self.non_string_cells += 1,
+            }
+        }
+
+        match ballot.iter().rposition(|c| *c != Choice::Undervote) {
+            Some(last_marked) => {
+                self.skipped_ranks += ballot[..last_marked]
+                    .iter()
+                    .filter(|c| **c == Choice::Undervote)
+                    .count() as u32;
+            }
+            None if anomalies.is_empty() => self.blank_ballots += 1,
+            None => {}
+        }
+    }
+
+    pub fn merge(&mut self, other: &QualityCounts) {
+        self.ballots += other.ballots;
+        self.blank_ballots += other.blank_ballots;
+        for (total, n) in self
+            .overvotes_by_rank
+            .iter_mut()
+            .zip(&other.overvotes_by_rank)
+        {
+            *total += n;
+        }
+        self.undervotes += other.undervotes;
+        self.skipped_ranks += other.skipped_ranks;
+        self.duplicate_rankings += other.duplicate_rankings;
+        self.write_ins += other.write_ins;
+        self.unknown_candidates += other.unknown_candidates;
+        self.non_string_cells += other.non_string_cells;
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct SourceQuality {
+    pub path: PathBuf,
+    #[serde(flatten)]
+    pub counts: QualityCounts,
+}
+
+/// The data-quality report of one contest, for all CVR workbooks and for each
+#[derive(Serialize, Clone, Debug)]
+pub struct QualityReport {
+    pub contest: String,
+    pub name: String,
+    pub total: QualityCounts,
+    pub sources: Vec<SourceQuality>,
+}
+
+impl QualityReport {
+    pub fn new(contest: &str, name: &str, ranks: usize) -> Self {
+        Self {
+            contest: contest.to_owned(),
+            name: name.to_owned(),
+            total: QualityCounts::new(ranks),
+            sources: vec![],
+        }
+    }
+
+    pub fn add_source(&mut self, path: PathBuf, counts: QualityCounts) {
+        self.total.merge(&counts);
+        self.sources.push(SourceQuality { path, counts });
+    }
+}
+
+/// A markdown table with a row per CVR workbook and one for the total
+impl fmt::Display for QualityReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Data quality of {} ({})", self.name, self.contest)?;
+        writeln!(
+            f,
+            "Source | Ballots | Blank | Overvotes by rank | Undervotes | Skipped ranks | Duplicates | Write-ins | Unknown IDs | Non-string cells"
+        )?;
+        writeln!(
+            f,
+            "--- | --- | --- | --- | --- | --- | --- | --- | --- | ---"
+        )?;
+        let total = PathBuf::from("Total");
+        let rows = self
+            .sources
+            .iter()
+            .map(|source| (&source.path, &source.counts))
+            .chain([(&total, &self.total)]);
+        for (path, counts) in rows {
+            let overvotes: Vec<String> = counts
+                .overvotes_by_rank
+                .iter()
+                .map(|n| n.to_string())
+                .collect();
+            writeln!(
+                f,
+                "{} | {} | {} | {} | {} | {} | {} | {} | {} | {}",
+                path.display(),
+                counts.ballots,
+                counts.blank_ballots,
+                overvotes.join(", "),
+                counts.undervotes,
+                counts.skipped_ranks,
+                counts.duplicate_rankings,
+                counts.write_ins,
+                counts.unknown_candidates,
+                counts.non_string_cells
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn anomaly(kind: AnomalyKind) -> Anomaly {
+        Anomaly {
+            path: PathBuf::from("cvr.xlsx"),
+            sheet: "Sheet1".to_owned(),
+            row: 2,
+            column: 1,
+            contest: "DEM Mayor".to_owned(),
+            kind,
+        }
+    }
+
+    fn add(counts: &mut QualityCounts, ballot: &[Choice], anomalies: &[Anomaly]) {
+        assert_eq!(ballot.len(), counts.overvotes_by_rank.len());
+        counts.add_ballot(ballot, anomalies);
+    }
+
+    #[test]
+    fn counts_every_kind_of_mark() {
+        use Choice::*;
+        let mut counts = QualityCounts::new(4);
+
+        add(&mut counts, &[Undervote; 4], &[]);
+        assert_eq!(counts.blank_ballots, 1);
+        assert_eq!(counts.undervotes, 4);
+
+        add(
+            &mut counts,
+            &[Candidate("A"), Undervote, Undervote, Candidate("B")],
+            &[],
+        );
+        assert_eq!(counts.skipped_ranks, 2);
+        assert_eq!(counts.undervotes, 6);
+
+        add(
+            &mut counts,
+            &[
+                Candidate("A"),
+                Candidate("B"),
+                Candidate("A"),
+                Candidate("A"),
+            ],
+            &[],
+        );
+        assert_eq!(counts.duplicate_rankings, 2);
+
+        add(&mut counts, &[Overvote, WriteIn, Overvote, Undervote], &[]);
+        assert_eq!(counts.overvotes_by_rank, [1, 0, 1, 0]);
+        assert_eq!(counts.write_ins, 1);
+        // a trailing undervote is not a skipped rank
+        assert_eq!(counts.skipped_ranks, 2);
+        assert_eq!(counts.undervotes, 7);
+
+        assert_eq!(counts.ballots, 4);
+        assert_eq!(counts.blank_ballots, 1);
+    }
+
+    #[test]
+    fn unreadable_cells_are_neither_undervotes_nor_blank() {
+        use Choice::*;
+        let mut counts = QualityCounts::new(3);
+
+        // every cell unreadable
+        let anomalies = [
+            anomaly(AnomalyKind::UnknownCandidate("Z".to_owned())),
+            anomaly(AnomalyKind::NonStringCell("1".to_owned())),
+            anomaly(AnomalyKind::NonStringCell("2".to_owned())),
+        ];
+        add(&mut counts, &[Undervote; 3], &anomalies);
+        assert_eq!(counts.undervotes, 0);
+        assert_eq!(counts.blank_ballots, 0);
+        assert_eq!(counts.unknown_candidates, 1);
+        assert_eq!(counts.non_string_cells, 2);
+
+        // an unreadable first choice is still a skipped rank
+        let anomalies = [anomaly(AnomalyKind::NonStringCell("3".to_owned()))];
+        add(
+            &mut counts,
+            &[Undervote, Candidate("A"), Undervote],
+            &anomalies,
+        );
+        assert_eq!(counts.undervotes, 1);
+        assert_eq!(counts.skipped_ranks, 1);
+        assert_eq!(counts.non_string_cells, 3);
+
+        assert_eq!(counts.ballots, 2);
+        assert_eq!(counts.blank_ballots, 0);
+    }
+}
@@ -0,0 +1,195 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    contest::ContestSelector,
+    core::Choice,
+    error::{Error, Result},
+    tabulate::Rules,
+};
+
+/// Everything that differs between elections, loaded from a TOML file. Paths
+/// are relative to the directory the program runs in
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct ElectionConfig {
+    /// Glob matching every CVR workbook, eg `./data/*.xlsx`
+    pub cvr: String,
+    /// Workbook mapping candidate IDs to names. It is never read as a CVR,
+    /// even if `cvr` matches it
+    pub candidates: PathBuf,
+    /// Sheet with the ballots in every CVR workbook
+    pub sheet: String,
+    /// Sheet with the candidate IDs and names
+    pub candidates_sheet: String,
+    /// Contests that `parse` extracts when none are given on the command line.
+    /// Each is a contest ID or a part of the contest name
+    pub contests: Vec<String>,
+    /// Only contests with this many ranks are extracted. `None` allows any number
+    pub ranks: Option<usize>,
+    pub header: HeaderFormat,
+    pub markers: Markers,
+    pub rules: Rules,
+    pub out_dir: PathBuf,
+}
+
+impl Default for ElectionConfig {
+    /// The 2025 NYC primary, unzipped into `./data`. The same as
+    /// `elections/nyc-2025-primary.toml`
+    fn default() -> Self {
+        Self {
+            cvr: "./data/*.xlsx".to_owned(),
+            candidates: PathBuf::from(
+                "./data/Primary Election 2025 - 06-24-2025_CandidacyID_To_Name.xlsx",
+            ),
+            sheet: "Sheet1".to_owned(),
+            candidates_sheet: "Sheet1".to_owned(),
+            contests: vec![],
+            ranks: None,
+            header: HeaderFormat::default(),
+            markers: Markers::default(),
+            rules: Rules::default(),
+            out_dir: PathBuf::from("./out"),
+        }
+    }
+}
+
+impl ElectionConfig {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|source| Error::File {
+            path: path.to_owned(),
+            source,
+        })?;
+        toml::from_str(&text).map_err(|source| Error::Config {
+            path: path.to_owned(),
+            source,
+        })
+    }
+
+    /// Every workbook matching `cvr`, except the candidate workbook. It is an
+    /// error if there are none
+    pub fn cvr_workbooks(&self) -> Result<Vec<PathBuf>> {
+        let mut excels = vec![];
+        for path in glob::glob(&self.cvr)? {
+            let path = path?;
+            if path.file_name() == self.candidates.file_name() {
+                continue;
+            }
+            excels.push(path);
+        }
+        if excels.is_empty() {
+            return Err(Error::Usage(format!("No CVR workbooks match {}", self.cvr)));
+        }
+        Ok(excels)
+    }
+
+    /// Looks for the CVR workbooks and the candidate workbook in `data_dir`,
+    /// keeping their file names
+    pub fn set_data_dir(&mut self, data_dir: &Path) {
+        let escaped = glob::Pattern::escape(&data_dir.to_string_lossy());
+        if let Some(pattern) = Path::new(&self.cvr).file_name() {
+            self.cvr = Path::new(&escaped)
+                .join(pattern)
+                .to_string_lossy()
+                .into_owned();
+        }
+        if let Some(file_name) = self.candidates.file_name() {
+            self.candidates = data_dir.join(file_name);
+        }
+    }
+
+    pub fn selectors(&self) -> Vec<ContestSelector> {
+        self.contests
+            .iter()
+            .map(|selector| ContestSelector::parse(selector))
+            .collect()
+    }
+}
+
+/// The words around the choice number in a contest header, eg
+/// "DEM Mayor Choice 1 of 5 Citywide (026916)"
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct HeaderFormat {
+    pub choice: String,
+    pub of: String,
+}
+
+impl Default for HeaderFormat {
+    fn default() -> Self {
+        Self {
+            choice: "Choice".to_owned(),
+            of: "of".to_owned(),
+        }
+    }
+}
+
+/// The cell values that aren't candidate IDs
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct Markers {
+    pub overvote: String,
+    pub undervote: String,
+    pub write_in: String,
+}
+
+impl Default for Markers {
+    fn default() -> Self {
+        Self {
+            overvote: "overvote".to_owned(),
+            undervote: "undervote".to_owned(),
+            write_in: "Write-in".to_owned(),
+        }
+    }
+}
+
+impl Markers {
+    /// The choice of a marker cell, or `None` if the cell isn't a marker
+    pub fn choice(&self, value: &str) -> Option<Choice<'static>> {
+        if value == self.overvote {
+            Some(Choice::Overvote)
+        } else if value == self.undervote {
+            Some(Choice::Undervote)
+        } else if value == self.write_in {
+            Some(Choice::WriteIn)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nyc_config_is_the_default() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("elections/nyc-2025-primary.toml");
+        assert_eq!(
+            ElectionConfig::load(path).unwrap(),
+            ElectionConfig::default()
+        );
+    }
+
+    #[test]
+    fn rejects_misspelled_rules() {
+        let config = toml::from_str::<ElectionConfig>("[rules]\nmax_skipped_ranks = 1").unwrap();
+        assert_eq!(config.rules.max_skipped_ranks, Some(1));
+        for config in [
+            "[rules]\nmax_skiped_ranks = 1",
+            "[rules]\ntie_break = { method = \"lot\", sed = 1 }",
+            "[header]\nchoise = \"Choice\"",
+            "output = \"./out\"",
+        ] {
+            assert!(
+                toml::from_str::<ElectionConfig>(config).is_err(),
+                "{config}"
+            );
+        }
+    }
+}
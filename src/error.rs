@@ -0,0 +1,68 @@
+use std::{io, path::PathBuf};
+
+use thiserror::Error;
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("{}: {source}", path.display())]
+    File { path: PathBuf, source: io::Error },
+    #[error("{}: {source}", path.display())]
+    Workbook {
+        path: PathBuf,
+        source: calamine::XlsxError,
+    },
+    #[error("{}, sheet {sheet}, row {row}, column {column}: {message}", path.display())]
+    Cell {
+        path: PathBuf,
+        sheet: String,
+        /// 1-based, as shown in a spreadsheet program
+        row: usize,
+        /// 1-based, as shown in a spreadsheet program
+        column: usize,
+        message: String,
+    },
+    #[error("Invalid config {}: {source}", path.display())]
+    Config {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+    #[error("Invalid CVR glob: {0}")]
+    Pattern(#[from] glob::PatternError),
+    #[error(transparent)]
+    Glob(#[from] glob::GlobError),
+    /// Ballots that cannot be encoded in or decoded from a `ballots.bin`
+    #[error("{0}")]
+    Encoding(String),
+    /// A `ballots.bin` that is not valid
+    #[error("{}: {message}", path.display())]
+    BallotFile { path: PathBuf, message: String },
+    #[error("Unknown {kind} {name}, use {expected}")]
+    UnknownName {
+        kind: &'static str,
+        name: String,
+        expected: String,
+    },
+    #[error("{method} is limited to {max} candidates, but there are {n_cands}")]
+    TooManyCandidates {
+        method: &'static str,
+        max: usize,
+        n_cands: usize,
+    },
+    #[error("No candidate {0} in the pairwise matrix")]
+    UnknownCandidate(String),
+    /// Contests `parse` could not write, after writing the others
+    #[error("Could not write contests {}", .0.join(", "))]
+    FailedContests(Vec<String>),
+    /// Arguments that don't make sense together, or that select nothing
+    #[error("{0}")]
+    Usage(String),
+    #[error("{}: {source}", path.display())]
+    Json {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    #[error(transparent)]
+    ProgressTemplate(#[from] indicatif::style::TemplateError),
+}
@@ -0,0 +1,136 @@
+use std::{
+    fs,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+
+use crate::{
+    core::writeable_file,
+    error::{Error, Result},
+    hierarchy::Hierarchy,
+    later_choices::LaterChoices,
+    rank_distributions::RankDistribution,
+};
+
+/// Writes the `rank-distributions.tsv` table to `out`, with candidates numbered
+/// in the order given
+pub fn write_rank_distributions_to(
+    mut out: impl Write,
+    distributions: &[RankDistribution],
+) -> io::Result<()> {
+    writeln!(out, "cand\trank\tfreq")?;
+    for (cand_idx, distribution) in distributions.iter().enumerate() {
+        for (idx, freq) in distribution.freqs.iter().enumerate() {
+            writeln!(out, "{cand_idx}\t{}\t{freq}", idx + 1)?;
+        }
+    }
+    out.flush()
+}
+
+/// Writes `rank-distributions.tsv`
+pub fn write_rank_distributions(path: &Path, distributions: &[RankDistribution]) -> Result<()> {
+    let file = BufWriter::new(writeable_file(path)?);
+    write_rank_distributions_to(file, distributions).map_err(|source| Error::File {
+        path: path.to_owned(),
+        source,
+    })
+}
+
+/// Writes the `tree.json` hierarchy to `out`
+pub fn write_hierarchy_to(mut out: impl Write, hierarchy: &Hierarchy) -> serde_json::Result<()> {
+    serde_json::to_writer(&mut out, hierarchy)?;
+    out.flush().map_err(serde_json::Error::io)
+}
+
+/// Writes `tree.json`
+pub fn write_hierarchy(path: &Path, hierarchy: &Hierarchy) -> Result<()> {
+    let file = BufWriter::new(writeable_file(path)?);
+    write_hierarchy_to(file, hierarchy).map_err(|source| Error::Json {
+        path: path.to_owned(),
+        source,
+    })
+}
+
+/// Writes `later_choices/<idx>.json` and `flows/<idx>.json` for every first
+/// choice, then `sorted_cands.json` and `weighted_matrices.json`, each to the
+/// sink `open` returns for its path relative to the contest directory
+pub fn write_later_choices_to<W: Write>(
+    mut open: impl FnMut(&Path) -> Result<W>,
+    later_choices: &LaterChoices,
+) -> Result<()> {
+    for (idx, first_choice) in later_choices.first_choices.iter().enumerate() {
+        write_json_to(
+            &mut open,
+            format!("later_choices/{idx}.json"),
+            &first_choice.rank_freqs,
+        )?;
+        write_json_to(&mut open, format!("flows/{idx}.json"), &first_choice.flows)?;
+    }
+
+    let n_voters: Vec<(&str, i64)> = later_choices
+        .first_choices
+        .iter()
+        .map(|first_choice| (first_choice.cand, first_choice.n_voters))
+        .collect();
+    write_json_to(&mut open, "sorted_cands.json", &n_voters)?;
+    write_json_to(
+        &mut open,
+        "weighted_matrices.json",
+        &later_choices.weighted_matrices,
+    )
+}
+
+/// Writes the later choices data into `out_dir`
+pub fn write_later_choices(out_dir: &Path, later_choices: &LaterChoices) -> Result<()> {
+    for dir in ["later_choices", "flows"] {
+        let path = out_dir.join(dir);
+        fs::create_dir_all(&path).map_err(|source| Error::File { path, source })?;
+    }
+    let open = |path: &Path| Ok(BufWriter::new(writeable_file(out_dir.join(path))?));
+    write_later_choices_to(open, later_choices).map_err(|e| match e {
+        Error::Json { path, source } => Error::Json {
+            path: out_dir.join(path),
+            source,
+        },
+        e => e,
+    })
+}
+
+fn write_json_to<W: Write, T: Serialize + ?Sized>(
+    open: &mut impl FnMut(&Path) -> Result<W>,
+    path: impl Into<PathBuf>,
+    value: &T,
+) -> Result<()> {
+    let path = path.into();
+    let mut out = open(&path)?;
+    serde_json::to_writer(&mut out, value)
+        .and_then(|()| out.flush().map_err(serde_json::Error::io))
+        .map_err(|source| Error::Json { path, source })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rank_distributions_to_any_sink() {
+        let distributions = [
+            RankDistribution {
+                cand: "A",
+                freqs: vec![3, 1, 0],
+            },
+            RankDistribution {
+                cand: "B",
+                freqs: vec![1, 2, 1],
+            },
+        ];
+        let mut tsv = vec![];
+        write_rank_distributions_to(&mut tsv, &distributions).unwrap();
+        assert_eq!(
+            String::from_utf8(tsv).unwrap(),
+            "cand\trank\tfreq\n0\t1\t3\n0\t2\t1\n0\t3\t0\n1\t1\t1\n1\t2\t2\n1\t3\t1\n"
+        );
+    }
+}
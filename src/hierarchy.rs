@@ -1,6 +1,16 @@
-use std::{collections::HashMap, error::Error};
+use std::collections::HashMap;
 
-use crate::core::{Node, NodeInternal, writeable_file};
+use serde::Serialize;
+
+use crate::core::{Node, NodeInternal, WeightedBallot};
+
+/// Every distinct sequence of ranked candidates as a tree, where each path
+/// from the root ends in an `Exhausted` leaf holding the number of voters
+#[derive(Serialize)]
+#[serde(transparent)]
+pub struct Hierarchy {
+    pub root: Node,
+}
 
 fn hmap_to_vec(k: String, n: &NodeInternal) -> Node {
     match n {
@@ -20,13 +30,11 @@ fn hmap_to_vec(k: String, n: &NodeInternal) -> Node {
     }
 }
 
-pub fn compute_hierarchy(all_ballots: &[[Option<&str>; 5]]) -> Result<(), Box<dyn Error>> {
-    println!("Writing hierarchy data");
-
+pub fn compute_hierarchy(all_ballots: &[WeightedBallot]) -> Hierarchy {
     let mut children: HashMap<String, NodeInternal> = HashMap::new();
     for ballot in all_ballots {
-        // flatten to ignore Nones
-        let flattened: Vec<_> = ballot.iter().flatten().collect();
+        // ignore everything that isn't a candidate
+        let flattened: Vec<_> = ballot.ranked().collect();
         if flattened.is_empty() {
             continue;
         }
@@ -53,9 +61,9 @@ pub fn compute_hierarchy(all_ballots: &[[Option<&str>; 5]]) -> Result<(), Box<dy
                     // exhausted can never have children
                     unreachable!()
                 };
-                *v += 1;
+                *v += i64::from(ballot.count);
             })
-            .or_insert(NodeInternal::Value(1));
+            .or_insert(NodeInternal::Value(i64::from(ballot.count)));
     }
 
     let children_vec: Vec<Node> = children
@@ -63,12 +71,11 @@ pub fn compute_hierarchy(all_ballots: &[[Option<&str>; 5]]) -> Result<(), Box<dy
         .map(|(k, n)| hmap_to_vec(k, &n))
         .collect();
 
-    let tree = Node {
-        name: "Root".to_owned(),
-        value: children_vec.iter().map(|node| node.value).sum(),
-        children: Some(children_vec),
-    };
-    let mut f = writeable_file("./out/tree.json")?;
-    serde_json::to_writer(&mut f, &tree)?;
-    Ok(())
+    Hierarchy {
+        root: Node {
+            name: "Root".to_owned(),
+            value: children_vec.iter().map(|node| node.value).sum(),
+            children: Some(children_vec),
+        },
+    }
 }
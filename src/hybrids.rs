@@ -0,0 +1,237 @@
+use serde::Serialize;
+
+use crate::{
+    condorcet::{PairwiseMatrix, TieCredit, compute_top_cycle, look_for_condorcet_winner},
+    core::WeightedBallot,
+    tabulate::{Elimination, IrvCount, Rules},
+};
+
+/// Methods that run an instant-runoff count, but elect the Condorcet winner
+/// or only count the Smith set
+#[derive(Serialize, Clone, Copy, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Hybrid {
+    /// Every round, candidates outside the Smith set of the continuing
+    /// candidates are eliminated, then the one with the fewest votes
+    TidemanAlternative,
+    /// Candidates outside the Smith set are eliminated before the count
+    SmithIrv,
+    /// The Condorcet winner is elected, and if there is none, the IRV winner
+    CondorcetIrv,
+    /// Every round, the Condorcet winner of the continuing candidates is elected
+    /// if there is one, otherwise the candidate with the fewest votes is eliminated
+    Benham,
+}
+
+impl Hybrid {
+    pub const ALL: [Hybrid; 4] = [
+        Hybrid::TidemanAlternative,
+        Hybrid::SmithIrv,
+        Hybrid::CondorcetIrv,
+        Hybrid::Benham,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Hybrid::TidemanAlternative => "Tideman's Alternative",
+            Hybrid::SmithIrv => "Smith//IRV",
+            Hybrid::CondorcetIrv => "Condorcet//IRV",
+            Hybrid::Benham => "Benham",
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct HybridResult<'a> {
+    pub method: Hybrid,
+    pub winner: Option<&'a str>,
+    /// The winner of plain IRV, eliminating one candidate per round
+    pub irv_winner: Option<&'a str>,
+    /// Candidates counted in every round, in candidate order
+    pub rounds: Vec<Vec<&'a str>>,
+    /// The first round where the method counts different candidates than
+    /// plain IRV, or elects a different winner. `None` if it elects the IRV
+    /// winner without ever eliminating a candidate that plain IRV kept
+    pub diverges_at_round: Option<usize>,
+}
+
+/// Runs a hybrid method. Each round eliminates the candidate with the fewest
+/// votes following `rules`, except that only one candidate is eliminated per round
+pub fn run_hybrid<'a>(
+    matrix: &PairwiseMatrix<'a>,
+    all_ballots: &[WeightedBallot],
+    rules: &Rules,
+    method: Hybrid,
+) -> HybridResult<'a> {
+    let rules = Rules {
+        elimination: Elimination::Single,
+        ..*rules
+    };
+    let (irv_winner, irv_rounds) = count(matrix, all_ballots, &rules, |_| Filter::None);
+    let (winner, rounds) = count(matrix, all_ballots, &rules, |round| match (method, round) {
+        (Hybrid::TidemanAlternative, _) | (Hybrid::SmithIrv, 1) => Filter::Smith,
+        (Hybrid::CondorcetIrv, 1) | (Hybrid::Benham, _) => Filter::CondorcetWinner,
+        _ => Filter::None,
+    });
+
+    let diverges_at_round = rounds
+        .iter()
+        .enumerate()
+        .find(|(idx, continuing)| irv_rounds.get(*idx) != Some(continuing))
+        .map(|(idx, _)| idx + 1)
+        .or_else(|| (winner != irv_winner).then_some(rounds.len()));
+
+    HybridResult {
+        method,
+        winner,
+        irv_winner,
+        rounds,
+        diverges_at_round,
+    }
+}
+
+enum Filter {
+    None,
+    /// Eliminate every candidate outside the Smith set
+    Smith,
+    /// Elect the Condorcet winner if there is one
+    CondorcetWinner,
+}
+
+/// Counts rounds until a winner is found, applying the filter chosen for each
+/// round to the continuing candidates before the count
+fn count<'a>(
+    matrix: &PairwiseMatrix<'a>,
+    all_ballots: &[WeightedBallot],
+    rules: &Rules,
+    filter: impl Fn(usize) -> Filter,
+) -> (Option<&'a str>, Vec<Vec<&'a str>>) {
+    // a single count, so ballots pass over eliminated candidates like in
+    // `tabulate`, and lots are drawn from one sequence
+    let mut count = IrvCount::new(matrix.cands(), all_ballots, rules);
+    let mut rounds = vec![];
+    loop {
+        let continuing = count.continuing();
+        let restricted = matrix.restrict(&continuing);
+        match filter(rounds.len() + 1) {
+            Filter::None => (),
+            Filter::Smith => {
+                let smith_set = compute_top_cycle(&restricted).smith_set;
+                let outside: Vec<&str> = continuing
+                    .iter()
+                    .filter(|cand| !smith_set.contains(cand))
+                    .copied()
+                    .collect();
+                count.eliminate(&outside);
+            }
+            Filter::CondorcetWinner => {
+                if let Some(winner) =
+                    look_for_condorcet_winner(&restricted, TieCredit::Half).strict_winner
+                {
+                    rounds.push(continuing);
+                    return (Some(winner), rounds);
+                }
+            }
+        }
+        rounds.push(count.continuing());
+
+        // with more than two candidates, each round eliminates exactly one
+        count.round();
+        if count.is_finished() {
+            return (count.winner(), rounds);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        condorcet::compute_pairwise_matrix,
+        core::Choice,
+        tabulate::{OvervotePolicy, TieBreakRule, tabulate},
+    };
+
+    fn run_all<'a>(cands: &[&'a str], ballots: &[WeightedBallot]) -> Vec<HybridResult<'a>> {
+        let matrix = compute_pairwise_matrix(cands, ballots);
+        let rules = Rules {
+            tie_break: TieBreakRule::FirstPreferences,
+            ..Rules::default()
+        };
+        Hybrid::ALL
+            .iter()
+            .map(|method| run_hybrid(&matrix, ballots, &rules, *method))
+            .collect()
+    }
+
+    fn ballot(ranks: &'static str, count: u32) -> WeightedBallot<'static> {
+        WeightedBallot {
+            choices: ranks.split(' ').map(Choice::Candidate).collect(),
+            count,
+        }
+    }
+
+    #[test]
+    fn plain_irv_matches_tabulate_across_eliminated_candidates() {
+        let ballots = [ballot("A", 4), ballot("B", 3), ballot("C B", 2)];
+        let cands = ["A", "B", "C"];
+        // passing over the eliminated C is not a skipped rank
+        let rules = Rules {
+            elimination: Elimination::Single,
+            tie_break: TieBreakRule::FirstPreferences,
+            overvote: OvervotePolicy::ExhaustBallot,
+            max_skipped_ranks: Some(0),
+        };
+
+        let matrix = compute_pairwise_matrix(&cands, &ballots);
+        let result = run_hybrid(&matrix, &ballots, &rules, Hybrid::CondorcetIrv);
+        assert_eq!(tabulate(&cands, &ballots, &rules).winner, Some("B"));
+        assert_eq!(result.irv_winner, Some("B"));
+    }
+
+    #[test]
+    fn hybrids_elect_the_condorcet_winner_irv_eliminates() {
+        let cands = ["Memphis", "Nashville", "Chattanooga", "Knoxville"];
+        let ballots = [
+            ballot("Memphis Nashville Chattanooga Knoxville", 42),
+            ballot("Nashville Chattanooga Knoxville Memphis", 26),
+            ballot("Chattanooga Knoxville Nashville Memphis", 15),
+            ballot("Knoxville Chattanooga Nashville Memphis", 17),
+        ];
+        for result in run_all(&cands, &ballots) {
+            let name = result.method.name();
+            assert_eq!(result.irv_winner, Some("Knoxville"), "{name}");
+            assert_eq!(result.winner, Some("Nashville"), "{name}");
+            assert_eq!(result.diverges_at_round, Some(1), "{name}");
+        }
+    }
+
+    #[test]
+    fn hybrids_agree_with_irv_in_a_cycle() {
+        // R beats P 6-3, P beats S 7-2 and S beats R 5-4. IRV eliminates S,
+        // whose ballots elect R
+        let ballots = [ballot("R P S", 4), ballot("P S R", 3), ballot("S R P", 2)];
+        let results = run_all(&["R", "P", "S"], &ballots);
+        for result in &results {
+            assert_eq!(result.irv_winner, Some("R"));
+            assert_eq!(result.winner, Some("R"), "{}", result.method.name());
+        }
+
+        let diverges: Vec<_> = results
+            .iter()
+            .map(|result| (result.method.name(), result.diverges_at_round))
+            .collect();
+        assert_eq!(
+            diverges,
+            [
+                // the Smith set of R and P is R alone, which IRV only finds by counting
+                ("Tideman's Alternative", Some(2)),
+                ("Smith//IRV", None),
+                ("Condorcet//IRV", None),
+                ("Benham", None),
+            ]
+        );
+        assert_eq!(results[0].rounds, [vec!["R", "P", "S"], vec!["R"]]);
+        assert_eq!(results[3].rounds, [vec!["R", "P", "S"], vec!["R", "P"]]);
+    }
+}
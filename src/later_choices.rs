@@ -1,92 +1,110 @@
-use std::{collections::HashMap, error::Error, fs, path::PathBuf};
-
-use crate::core::writeable_file;
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::core::WeightedBallot;
+
+/// Where the voters that ranked a candidate first ranked everyone else
+#[derive(Serialize)]
+pub struct FirstChoice<'a> {
+    pub cand: &'a str,
+    /// Voters that ranked this candidate first
+    pub n_voters: i64,
+    /// For every other candidate in order, then for exhausted ballots, the
+    /// voters that ranked them at each later rank
+    pub rank_freqs: Vec<Vec<i64>>,
+    /// Voters moving from one rank to the next, eg from `1: Smith` to `2: Jones`
+    pub flows: HashMap<String, HashMap<String, i64>>,
+}
 
-pub fn compute_later_choices(
-    all_ballots: &[[Option<&str>; 5]],
-    cands_to_n_wins: &[(&&str, &i32)],
-) -> Result<(), Box<dyn Error>> {
-    println!("Writing later choices data");
+#[derive(Serialize)]
+pub struct LaterChoices<'a> {
+    pub first_choices: Vec<FirstChoice<'a>>,
+    /// How strongly the voters of each candidate transferred to every other
+    /// candidate and to exhausted, under each score of `calc_scores`. Each
+    /// matrix has a row of zeroes for exhausted at the end
+    pub weighted_matrices: [Vec<Vec<f32>>; 5],
+}
 
-    let mut all_n_voters = vec![];
+/// The later choices of the voters of every candidate, in the order of `cands`
+pub fn compute_later_choices<'a>(
+    all_ballots: &[WeightedBallot],
+    cands: &[&'a str],
+) -> LaterChoices<'a> {
+    let ranks = all_ballots
+        .iter()
+        .map(|ballot| ballot.choices.len())
+        .max()
+        .unwrap_or(0);
+    // ranks after the first choice
+    let later_ranks = ranks.saturating_sub(1);
+
+    let mut first_choices = vec![];
     let mut matrices = [vec![], vec![], vec![], vec![], vec![]];
 
-    for (idx, (first_choice_cand, _)) in cands_to_n_wins.iter().enumerate() {
-        let later_choices: Vec<Vec<&str>> = all_ballots
+    for first_choice_cand in cands {
+        // later choices of the ballots and the number of voters that cast them
+        let later_choices: Vec<(Vec<&str>, i64)> = all_ballots
             .iter()
-            .filter(|ballot| ballot.iter().flatten().next() == Some(first_choice_cand))
+            .filter(|ballot| ballot.ranked().next() == Some(*first_choice_cand))
             .map(|ballot| {
-                ballot
-                    .iter()
-                    .flatten()
-                    .filter(|c| c != first_choice_cand)
-                    .copied()
-                    .collect()
+                let choices = ballot.ranked().filter(|c| c != first_choice_cand).collect();
+                (choices, i64::from(ballot.count))
             })
             .collect();
 
-        let mut cand_rank_freqs: Vec<[i64; 4]> = vec![];
+        let mut cand_rank_freqs: Vec<Vec<i64>> = vec![];
         let mut matrix_row = vec![];
-        for (other_cand, _) in cands_to_n_wins.iter() {
+        for other_cand in cands {
             if other_cand == first_choice_cand {
+                // one zero for each score in `calc_scores`
                 matrix_row.push([0.; 5]);
                 continue;
             }
 
             // for voters that ranked first_choice_cand first, find the position they ranked other_cand
-            let ranks = later_choices
-                .iter()
-                .map(|ballot| ballot.iter().position(|c| c == *other_cand));
-
-            let mut freqs = [0; 4];
-            for rank in ranks.flatten() {
-                freqs[rank] += 1;
+            let mut freqs = vec![0; later_ranks];
+            for (ballot, count) in &later_choices {
+                if let Some(rank) = ballot.iter().position(|c| c == other_cand) {
+                    freqs[rank] += count;
+                }
             }
 
-            cand_rank_freqs.push(freqs);
-
             matrix_row.push(calc_scores(&freqs));
+            cand_rank_freqs.push(freqs);
         }
 
         // treat exhausted as if it's a separate candidate and count
         // the frequencies of ranks where ballots become exhausted
-        let mut exhausted_freqs = [0; 4];
-        for ballot in &later_choices {
+        let mut exhausted_freqs = vec![0; later_ranks];
+        for (ballot, count) in &later_choices {
             for (idx, c) in exhausted_freqs.iter_mut().enumerate() {
                 if ballot.len() <= idx {
-                    *c += 1;
+                    *c += count;
                 }
             }
         }
-        cand_rank_freqs.push(exhausted_freqs);
         matrix_row.push(calc_scores(&exhausted_freqs));
-
-        let mut path = PathBuf::from("./out/later_choices");
-        let _ = fs::create_dir_all(&path);
-        path.push(format!("{idx}.json"));
-
-        let mut f = writeable_file(path)?;
-        serde_json::to_writer(&mut f, &cand_rank_freqs)?;
+        cand_rank_freqs.push(exhausted_freqs);
 
         for (idx, matrix) in matrices.iter_mut().enumerate() {
             let r: Vec<f32> = matrix_row.iter().map(|v| v[idx]).collect();
             matrix.push(r);
         }
 
-        let n_voters = later_choices.len();
-        all_n_voters.push((**first_choice_cand, n_voters));
+        let n_voters: i64 = later_choices.iter().map(|(_, count)| count).sum();
 
         let mut flows: HashMap<String, HashMap<String, i64>> = HashMap::new();
 
-        for ballot in later_choices {
+        for (ballot, count) in later_choices {
             let mut i = 0;
             // iterate up to ballot.len() + 1 to count exhausted ballots
             while i < ballot.len() + 1 {
-                // we don't need to count "6. Exhausted"
-                // because by count 5, all preferences have been indicated.
+                // we don't need to count "{ranks + 1}. Exhausted"
+                // because by count `ranks`, all preferences have been indicated.
                 // so the next count will be "none".
                 // i + 2 is the number for the `to` field
-                if i + 2 == 6 {
+                if i + 2 == ranks + 1 {
                     break;
                 }
 
@@ -109,11 +127,13 @@ pub fn compute_later_choices(
                 flows
                     .entry(from)
                     .and_modify(|hmap| {
-                        hmap.entry(to.clone()).and_modify(|c| *c += 1).or_insert(1);
+                        hmap.entry(to.clone())
+                            .and_modify(|c| *c += count)
+                            .or_insert(count);
                     })
                     .or_insert_with(|| {
                         let mut hmap = HashMap::new();
-                        hmap.insert(to, 1_i64);
+                        hmap.insert(to, count);
                         hmap
                     });
 
@@ -121,26 +141,22 @@ pub fn compute_later_choices(
             }
         }
 
-        let mut path = PathBuf::from("./out/flows");
-        let _ = fs::create_dir_all(&path);
-        path.push(format!("{idx}.json"));
-
-        let mut f = writeable_file(path)?;
-        serde_json::to_writer(&mut f, &flows)?;
+        first_choices.push(FirstChoice {
+            cand: first_choice_cand,
+            n_voters,
+            rank_freqs: cand_rank_freqs,
+            flows,
+        });
     }
 
-    let mut f = writeable_file("./out/sorted_cands.json")?;
-    serde_json::to_writer(&mut f, &all_n_voters)?;
-
-    println!("Writing weighted matrices data");
     for matrix in matrices.iter_mut() {
-        matrix.push(vec![0.0; cands_to_n_wins.len() + 1]);
+        matrix.push(vec![0.0; cands.len() + 1]);
     }
 
-    let mut f = writeable_file("./out/weighted_matrices.json")?;
-    serde_json::to_writer(&mut f, &matrices)?;
-
-    Ok(())
+    LaterChoices {
+        first_choices,
+        weighted_matrices: matrices,
+    }
 }
 
 fn calc_scores(rank_freqs: &[i64]) -> [f32; 5] {
@@ -157,10 +173,11 @@ fn calc_scores(rank_freqs: &[i64]) -> [f32; 5] {
             first_transfer_score += freq;
         }
 
+        // with 4 later ranks:
         // if idx == 0, +4
         // if idx == 1, +3
         // if idx == 2, +2
-        borda_score += freq * (4 - idx as i64);
+        borda_score += freq * ((rank_freqs.len() - idx) as i64);
 
         let f = *freq as f32;
 
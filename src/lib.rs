@@ -1,5 +1,18 @@
+pub mod ballot_file;
+pub mod bootstrap;
+pub mod condorcet;
+pub mod condorcet_methods;
+pub mod contest;
 pub mod core;
+pub mod cvr;
+pub mod data_quality;
+pub mod election;
+pub mod error;
+pub mod export;
 pub mod hierarchy;
+pub mod hybrids;
 pub mod later_choices;
+pub mod methods;
 pub mod rank_distributions;
-pub mod condorcet;
\ No newline at end of file
+pub mod stv;
+pub mod tabulate;
@@ -0,0 +1,453 @@
+use std::{cmp::Reverse, collections::HashMap};
+
+use serde::Serialize;
+
+use crate::{condorcet::ranked_indexes, core::WeightedBallot};
+
+/// How Borda points are given when a ballot does not rank every continuing candidate.
+/// With `n` continuing candidates, a candidate ranked in position `i` (from 0)
+/// of the continuing candidates on a ballot that ranks `k` of them gets:
+#[derive(Serialize, Clone, Copy, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum BordaVariant {
+    /// `n - 1 - i` points, and the unranked candidates get 0
+    Truncated,
+    /// `n - 1 - i` points, and the unranked candidates share the points of the
+    /// positions left over equally, `(n - k - 1) / 2` each
+    Averaged,
+    /// `k - i` points (the modified Borda count), and the unranked candidates
+    /// get 0, so ranking fewer candidates gives fewer points
+    Modified,
+}
+
+/// An election method that is run on the ballots rather than the pairwise matrix
+#[derive(Serialize, Clone, Copy, Debug)]
+#[serde(rename_all = "snake_case", tag = "method", content = "borda")]
+pub enum BallotMethod {
+    /// The candidate with the most Borda points wins
+    Borda(BordaVariant),
+    /// The candidate ranked last by the most voters is eliminated, until a
+    /// candidate has a majority of first preferences
+    Coombs,
+    /// The candidate with the fewest Borda points is eliminated, recounting
+    /// the points every round
+    Baldwin(BordaVariant),
+    /// Every candidate with fewer Borda points than the average is eliminated,
+    /// recounting the points every round
+    Nanson(BordaVariant),
+    /// The first choices are counted, then the first and second choices, and
+    /// so on until a candidate is ranked by a majority
+    Bucklin,
+    /// Instant-runoff where the candidate of the bottom two that loses the
+    /// pairwise matchup between them is eliminated
+    BottomTwoRunoff,
+}
+
+impl BallotMethod {
+    /// Every method, run by `nyc-irv analyze`
+    pub const ALL: [BallotMethod; 8] = [
+        BallotMethod::Borda(BordaVariant::Truncated),
+        BallotMethod::Borda(BordaVariant::Averaged),
+        BallotMethod::Borda(BordaVariant::Modified),
+        BallotMethod::Coombs,
+        BallotMethod::Baldwin(BordaVariant::Averaged),
+        BallotMethod::Nanson(BordaVariant::Averaged),
+        BallotMethod::Bucklin,
+        BallotMethod::BottomTwoRunoff,
+    ];
+
+    pub fn name(self) -> String {
+        match self {
+            BallotMethod::Borda(variant) => format!("Borda ({variant:?})"),
+            BallotMethod::Coombs => "Coombs".to_owned(),
+            BallotMethod::Baldwin(variant) => format!("Baldwin ({variant:?})"),
+            BallotMethod::Nanson(variant) => format!("Nanson ({variant:?})"),
+            BallotMethod::Bucklin => "Bucklin".to_owned(),
+            BallotMethod::BottomTwoRunoff => "Bottom-two-runoff IRV".to_owned(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct MethodRound<'a> {
+    pub round: usize,
+    /// First preferences of every continuing candidate, most first
+    pub first_preferences: Vec<(&'a str, u32)>,
+    /// The scores the method decided on, highest first. Borda points for Borda,
+    /// Baldwin and Nanson, last place votes for Coombs, votes in the top ranks
+    /// counted so far for Bucklin, and first preferences for bottom-two-runoff
+    pub scores: Vec<(&'a str, f64)>,
+    /// Candidates eliminated at the end of this round
+    pub eliminated: Vec<&'a str>,
+}
+
+#[derive(Serialize)]
+pub struct MethodResult<'a> {
+    pub method: BallotMethod,
+    pub winner: Option<&'a str>,
+    pub rounds: Vec<MethodRound<'a>>,
+}
+
+/// Runs `method` on the ballots. Only the ranked candidates of a ballot are
+/// used, so overvotes, undervotes and write-ins are passed over. Ties for
+/// elimination are lost by the candidate that is last in `sorted_cands`. If no
+/// ballot ranks a candidate, there are no rounds and no winner
+pub fn run_method<'a>(
+    sorted_cands: &[&'a str],
+    all_ballots: &[WeightedBallot],
+    method: BallotMethod,
+) -> MethodResult<'a> {
+    let cand_to_idx: HashMap<&str, usize> = sorted_cands
+        .iter()
+        .enumerate()
+        .map(|(idx, cand)| (*cand, idx))
+        .collect();
+    let ballots: Vec<(Vec<usize>, u32)> = all_ballots
+        .iter()
+        .map(|ballot| (ranked_indexes(ballot, &cand_to_idx), ballot.count))
+        .collect();
+    if ballots.iter().all(|(ranked, _)| ranked.is_empty()) {
+        return MethodResult {
+            method,
+            winner: None,
+            rounds: vec![],
+        };
+    }
+
+    let mut count = Count {
+        sorted_cands,
+        ballots: &ballots,
+        continuing: vec![true; sorted_cands.len()],
+        rounds: vec![],
+    };
+    let winner = match method {
+        BallotMethod::Borda(variant) => {
+            let scores = count.borda_scores(variant);
+            count.push_round(&scores, vec![]);
+            count.highest(&scores)
+        }
+        BallotMethod::Coombs => loop {
+            let first_prefs = count.first_preferences();
+            let n_continuing_ballots: u32 = first_prefs.iter().sum();
+            let majority = (0..sorted_cands.len())
+                .find(|idx| count.continuing[*idx] && first_prefs[*idx] * 2 > n_continuing_ballots);
+            let scores = count.last_places();
+            if majority.is_some() || count.n_continuing() <= 1 {
+                count.push_round(&scores, vec![]);
+                break majority.or_else(|| count.highest(&scores));
+            }
+            // of the candidates tied for the most last places, the last one loses
+            let order = count.ordered(&scores);
+            let loser = order
+                .iter()
+                .rev()
+                .find(|idx| scores[**idx] == scores[order[0]])
+                .copied();
+            count.push_round(&scores, loser.into_iter().collect());
+        },
+        BallotMethod::Baldwin(variant) => loop {
+            let scores = count.borda_scores(variant);
+            if count.n_continuing() <= 1 {
+                count.push_round(&scores, vec![]);
+                break count.highest(&scores);
+            }
+            let loser = count.lowest(&scores).into_iter().collect();
+            count.push_round(&scores, loser);
+        },
+        BallotMethod::Nanson(variant) => loop {
+            let scores = count.borda_scores(variant);
+            let continuing: Vec<usize> = (0..sorted_cands.len())
+                .filter(|idx| count.continuing[*idx])
+                .collect();
+            let mean =
+                continuing.iter().map(|idx| scores[*idx]).sum::<f64>() / continuing.len() as f64;
+            let losers: Vec<usize> = continuing
+                .iter()
+                .copied()
+                .filter(|idx| scores[*idx] < mean)
+                .collect();
+            // if nobody is below the average, every continuing candidate is tied
+            if count.n_continuing() <= 1 || losers.is_empty() {
+                count.push_round(&scores, vec![]);
+                break count.highest(&scores);
+            }
+            count.push_round(&scores, losers);
+        },
+        BallotMethod::Bucklin => {
+            let n_voters: u32 = ballots
+                .iter()
+                .filter(|(ranked, _)| !ranked.is_empty())
+                .map(|(_, n)| n)
+                .sum();
+            let max_ranks = ballots.iter().map(|(ranked, _)| ranked.len()).max();
+            let mut scores = vec![0.; sorted_cands.len()];
+            let mut winner = None;
+            for rank in 0..max_ranks.unwrap_or(0) {
+                for (ranked, n) in &ballots {
+                    if let Some(idx) = ranked.get(rank) {
+                        scores[*idx] += f64::from(*n);
+                    }
+                }
+                count.push_round(&scores, vec![]);
+                winner = count.highest(&scores);
+                if scores.iter().any(|score| *score * 2. > f64::from(n_voters)) {
+                    break;
+                }
+            }
+            winner
+        }
+        BallotMethod::BottomTwoRunoff => loop {
+            let first_prefs = count.first_preferences();
+            let scores: Vec<f64> = first_prefs.iter().map(|v| f64::from(*v)).collect();
+            if count.n_continuing() <= 1 {
+                count.push_round(&scores, vec![]);
+                break count.highest(&scores);
+            }
+            let order = count.ordered(&scores);
+            let (upper, lower) = (order[order.len() - 2], order[order.len() - 1]);
+            let (upper_wins, lower_wins) = count.matchup(upper, lower);
+            let loser = if lower_wins > upper_wins {
+                upper
+            } else {
+                lower
+            };
+            count.push_round(&scores, vec![loser]);
+        },
+    };
+
+    MethodResult {
+        method,
+        winner: winner.map(|idx| sorted_cands[idx]),
+        rounds: count.rounds,
+    }
+}
+
+/// The state of a count that eliminates candidates
+struct Count<'a, 'b> {
+    sorted_cands: &'b [&'a str],
+    /// Indexes of the ranked candidates, and the number of voters
+    ballots: &'b [(Vec<usize>, u32)],
+    continuing: Vec<bool>,
+    rounds: Vec<MethodRound<'a>>,
+}
+
+impl<'a> Count<'a, '_> {
+    fn n_continuing(&self) -> usize {
+        self.continuing.iter().filter(|c| **c).count()
+    }
+
+    /// The continuing candidates ranked on a ballot, in order
+    fn continuing_ranked<'c>(&'c self, ranked: &'c [usize]) -> impl Iterator<Item = usize> + 'c {
+        ranked.iter().copied().filter(|idx| self.continuing[*idx])
+    }
+
+    fn first_preferences(&self) -> Vec<u32> {
+        let mut votes = vec![0; self.sorted_cands.len()];
+        for (ranked, n) in self.ballots {
+            if let Some(idx) = self.continuing_ranked(ranked).next() {
+                votes[idx] += n;
+            }
+        }
+        votes
+    }
+
+    fn borda_scores(&self, variant: BordaVariant) -> Vec<f64> {
+        let n_cands = self.n_continuing();
+        let mut scores = vec![0.; self.sorted_cands.len()];
+        for (ranked, n) in self.ballots {
+            let ranked: Vec<usize> = self.continuing_ranked(ranked).collect();
+            let k = ranked.len();
+            for (pos, idx) in ranked.iter().enumerate() {
+                let points = match variant {
+                    BordaVariant::Truncated | BordaVariant::Averaged => n_cands - 1 - pos,
+                    BordaVariant::Modified => k - pos,
+                };
+                scores[*idx] += points as f64 * f64::from(*n);
+            }
+            if let BordaVariant::Averaged = variant {
+                let shared = (n_cands - k).saturating_sub(1) as f64 / 2.;
+                for idx in (0..scores.len()).filter(|idx| self.continuing[*idx]) {
+                    if !ranked.contains(&idx) {
+                        scores[idx] += shared * f64::from(*n);
+                    }
+                }
+            }
+        }
+        scores
+    }
+
+    /// Number of voters ranking each candidate last of the continuing candidates.
+    /// The unranked candidates of a ballot share its last place equally
+    fn last_places(&self) -> Vec<f64> {
+        let continuing: Vec<usize> = (0..self.sorted_cands.len())
+            .filter(|idx| self.continuing[*idx])
+            .collect();
+        let mut scores = vec![0.; self.sorted_cands.len()];
+        for (ranked, n) in self.ballots {
+            let ranked: Vec<usize> = self.continuing_ranked(ranked).collect();
+            let unranked: Vec<usize> = continuing
+                .iter()
+                .copied()
+                .filter(|idx| !ranked.contains(idx))
+                .collect();
+            match (unranked.as_slice(), ranked.last()) {
+                ([], Some(last)) => scores[*last] += f64::from(*n),
+                ([], None) => (),
+                (unranked, _) => {
+                    for idx in unranked {
+                        scores[*idx] += f64::from(*n) / unranked.len() as f64;
+                    }
+                }
+            }
+        }
+        scores
+    }
+
+    /// Voters preferring `a` over `b`, and `b` over `a`
+    fn matchup(&self, a: usize, b: usize) -> (u32, u32) {
+        let mut votes = (0, 0);
+        for (ranked, n) in self.ballots {
+            match self
+                .continuing_ranked(ranked)
+                .find(|idx| *idx == a || *idx == b)
+            {
+                Some(idx) if idx == a => votes.0 += n,
+                Some(_) => votes.1 += n,
+                None => (),
+            }
+        }
+        votes
+    }
+
+    /// The continuing candidates, highest score first. Tied candidates are
+    /// kept in the order of `sorted_cands`
+    fn ordered(&self, scores: &[f64]) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..self.sorted_cands.len())
+            .filter(|idx| self.continuing[*idx])
+            .collect();
+        order.sort_by(|a, b| scores[*b].total_cmp(&scores[*a]));
+        order
+    }
+
+    fn highest(&self, scores: &[f64]) -> Option<usize> {
+        self.ordered(scores).first().copied()
+    }
+
+    fn lowest(&self, scores: &[f64]) -> Option<usize> {
+        self.ordered(scores).last().copied()
+    }
+
+    fn push_round(&mut self, scores: &[f64], eliminated: Vec<usize>) {
+        let first_prefs = self.first_preferences();
+        let mut first_preferences: Vec<(&str, u32)> = (0..self.sorted_cands.len())
+            .filter(|idx| self.continuing[*idx])
+            .map(|idx| (self.sorted_cands[idx], first_prefs[idx]))
+            .collect();
+        first_preferences.sort_by_key(|(_, v)| Reverse(*v));
+
+        let round = MethodRound {
+            round: self.rounds.len() + 1,
+            first_preferences,
+            scores: self
+                .ordered(scores)
+                .iter()
+                .map(|idx| (self.sorted_cands[*idx], scores[*idx]))
+                .collect(),
+            eliminated: eliminated
+                .iter()
+                .map(|idx| self.sorted_cands[*idx])
+                .collect(),
+        };
+        self.rounds.push(round);
+        for idx in eliminated {
+            self.continuing[idx] = false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::Choice;
+
+    fn ballot(ranks: &'static str, count: u32) -> WeightedBallot<'static> {
+        WeightedBallot {
+            choices: ranks.split(' ').map(Choice::Candidate).collect(),
+            count,
+        }
+    }
+
+    #[test]
+    fn every_method_elects_nashville_in_tennessee() {
+        let cands = ["Memphis", "Nashville", "Chattanooga", "Knoxville"];
+        let ballots = [
+            ballot("Memphis Nashville Chattanooga Knoxville", 42),
+            ballot("Nashville Chattanooga Knoxville Memphis", 26),
+            ballot("Chattanooga Knoxville Nashville Memphis", 15),
+            ballot("Knoxville Chattanooga Nashville Memphis", 17),
+        ];
+        for method in BallotMethod::ALL {
+            let result = run_method(&cands, &ballots, method);
+            assert_eq!(result.winner, Some("Nashville"), "{}", method.name());
+        }
+        // Bucklin finds a majority once the second choices are counted
+        let bucklin = run_method(&cands, &ballots, BallotMethod::Bucklin);
+        assert_eq!(bucklin.rounds.len(), 2);
+        assert_eq!(bucklin.rounds[1].scores[0], ("Nashville", 68.));
+    }
+
+    #[test]
+    fn borda_variants_score_truncated_ballots() {
+        let ballots = [ballot("A", 1), ballot("B C A", 1)];
+        let scores = |variant| {
+            let result = run_method(&["A", "B", "C"], &ballots, BallotMethod::Borda(variant));
+            result.rounds[0].scores.clone()
+        };
+        assert_eq!(
+            scores(BordaVariant::Truncated),
+            [("A", 2.), ("B", 2.), ("C", 1.)]
+        );
+        assert_eq!(
+            scores(BordaVariant::Averaged),
+            [("B", 2.5), ("A", 2.), ("C", 1.5)]
+        );
+        assert_eq!(
+            scores(BordaVariant::Modified),
+            [("B", 3.), ("A", 2.), ("C", 2.)]
+        );
+    }
+
+    #[test]
+    fn elimination_ties_are_lost_by_the_last_candidate() {
+        // B and C tie for the fewest Borda points
+        let ballots = [ballot("A B C", 2), ballot("A C B", 2)];
+        let baldwin = run_method(
+            &["A", "B", "C"],
+            &ballots,
+            BallotMethod::Baldwin(BordaVariant::Truncated),
+        );
+        assert_eq!(baldwin.rounds[0].eliminated, ["C"]);
+        assert_eq!(baldwin.rounds[1].eliminated, ["B"]);
+        assert_eq!(baldwin.winner, Some("A"));
+
+        // B and C tie for the most last places, as they share the last place of "A"
+        let ballots = [ballot("B A C", 2), ballot("C A B", 2), ballot("A", 1)];
+        let coombs = run_method(&["A", "B", "C"], &ballots, BallotMethod::Coombs);
+        assert_eq!(coombs.rounds[0].scores[..2], [("B", 2.5), ("C", 2.5)]);
+        assert_eq!(coombs.rounds[0].eliminated, ["C"]);
+        assert_eq!(coombs.winner, Some("A"));
+    }
+
+    #[test]
+    fn blank_ballots_elect_nobody() {
+        let ballots = [WeightedBallot {
+            choices: vec![Choice::Undervote, Choice::Overvote],
+            count: 5,
+        }];
+        for method in BallotMethod::ALL {
+            let result = run_method(&["A", "B"], &ballots, method);
+            assert_eq!(result.winner, None, "{}", method.name());
+            assert!(result.rounds.is_empty());
+        }
+    }
+}
@@ -1,33 +1,42 @@
-use std::{io::Write, path::PathBuf};
+use serde::Serialize;
 
-use crate::core::writeable_file;
+use crate::core::{Choice, WeightedBallot};
 
-pub fn compute_rank_distributions(
-    all_ballots: &[[Option<&str>; 5]],
-    cands_to_n_wins: &[(&&str, &i32)],
-) -> Result<(), Box<dyn std::error::Error>> {
-    println!("Writing distribution of ranks");
+/// The number of voters that ranked a candidate in each position
+#[derive(Serialize)]
+pub struct RankDistribution<'a> {
+    pub cand: &'a str,
+    /// Voters for every rank, first rank first. The last position counts the
+    /// voters that did not rank the candidate
+    pub freqs: Vec<u32>,
+}
 
-    let path = PathBuf::from("./out/rank-distributions.tsv");
-    let mut f = writeable_file(path)?;
-    f.write_all(b"cand\trank\tfreq\n")?;
+/// The rank distribution of every candidate, in the order of `cands`
+pub fn compute_rank_distributions<'a>(
+    all_ballots: &[WeightedBallot],
+    cands: &[&'a str],
+) -> Vec<RankDistribution<'a>> {
+    let ranks = all_ballots
+        .iter()
+        .map(|ballot| ballot.choices.len())
+        .max()
+        .unwrap_or(0);
 
-    for (cand_idx, (cand, _)) in cands_to_n_wins.iter().enumerate() {
-        let mut position_freqs = [0; 6];
-        for ballot in all_ballots {
-            let pos = ballot
-                .iter()
-                .position(|choice| *choice == Some(**cand));
-            match pos {
-                Some(p) => position_freqs[p] += 1,
-                None => position_freqs[5] += 1,
+    cands
+        .iter()
+        .map(|cand| {
+            let mut freqs = vec![0; ranks + 1];
+            for ballot in all_ballots {
+                let pos = ballot
+                    .choices
+                    .iter()
+                    .position(|choice| *choice == Choice::Candidate(cand));
+                match pos {
+                    Some(p) => freqs[p] += ballot.count,
+                    None => freqs[ranks] += ballot.count,
+                }
             }
-        }
-
-        for (idx, freq) in position_freqs.iter().enumerate() {
-            f.write_all(format!("{cand_idx}\t{}\t{freq}\n", idx + 1).as_bytes())?;
-        }
-    }
-
-    Ok(())
+            RankDistribution { cand, freqs }
+        })
+        .collect()
 }
@@ -0,0 +1,497 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::{
+    core::WeightedBallot,
+    error::{Error, Result},
+};
+
+/// How the surplus of an elected candidate is passed on
+#[derive(Serialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum SurplusTransfer {
+    /// Only the last parcel of ballots the candidate received is transferred
+    Gregory,
+    /// Every ballot of the candidate is transferred at its current weight
+    /// times the surplus over the candidate's votes
+    WeightedInclusiveGregory,
+    /// Elected candidates keep a share of every ballot reaching them, which is
+    /// iterated until each keeps exactly a quota, and the rest passes on
+    Meek,
+}
+
+impl SurplusTransfer {
+    pub fn from_name(name: &str) -> Result<Self> {
+        match name {
+            "gregory" => Ok(SurplusTransfer::Gregory),
+            "wig" => Ok(SurplusTransfer::WeightedInclusiveGregory),
+            "meek" => Ok(SurplusTransfer::Meek),
+            _ => Err(Error::UnknownName {
+                kind: "surplus transfer",
+                name: name.to_owned(),
+                expected: "gregory, wig or meek".to_owned(),
+            }),
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Copy, Debug)]
+pub struct StvRules {
+    pub seats: usize,
+    pub transfer: SurplusTransfer,
+}
+
+#[derive(Serialize)]
+pub struct StvRound<'a> {
+    pub round: usize,
+    /// The Droop quota. For Meek it is recalculated from the votes that are not
+    /// exhausted every round
+    pub quota: f64,
+    /// Votes of every hopeful and elected candidate, most votes first
+    pub tallies: Vec<(&'a str, f64)>,
+    /// Votes that no longer count towards any candidate, cumulative
+    pub exhausted: f64,
+    /// Candidates that reached the quota in this round, or filled the last
+    /// seats because there were no more candidates than seats
+    pub elected: Vec<&'a str>,
+    pub eliminated: Vec<&'a str>,
+    /// The candidate whose surplus was transferred at the end of this round,
+    /// and the fraction of their weight the transferred ballots kept
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub surplus_transfer: Option<(&'a str, f64)>,
+    /// Share of the votes reaching each elected candidate that it keeps, for Meek
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub keep_factors: Vec<(&'a str, f64)>,
+}
+
+#[derive(Serialize)]
+pub struct StvResult<'a> {
+    pub rules: StvRules,
+    /// Elected candidates in the order they were elected
+    pub elected: Vec<&'a str>,
+    pub rounds: Vec<StvRound<'a>>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Status {
+    Hopeful,
+    Elected,
+    Excluded,
+}
+
+/// Meek stops iterating once every elected candidate is this close to the quota
+const MEEK_TOLERANCE: f64 = 1e-6;
+const MEEK_MAX_ITERATIONS: usize = 1000;
+
+/// Runs a Single Transferable Vote count. Only the ranked candidates of a
+/// ballot are used, so overvotes, undervotes and write-ins are passed over.
+/// Candidates tied for the fewest votes are eliminated in reverse order of `sorted_cands`
+pub fn tabulate_stv<'a>(
+    sorted_cands: &[&'a str],
+    all_ballots: &[WeightedBallot],
+    rules: &StvRules,
+) -> StvResult<'a> {
+    let cand_to_idx: HashMap<&str, usize> = sorted_cands
+        .iter()
+        .enumerate()
+        .map(|(idx, cand)| (*cand, idx))
+        .collect();
+    let ballots: Vec<(Vec<usize>, f64)> = all_ballots
+        .iter()
+        .map(|ballot| {
+            let mut ranked: Vec<usize> = vec![];
+            for idx in ballot.ranked().filter_map(|cand| cand_to_idx.get(cand)) {
+                if !ranked.contains(idx) {
+                    ranked.push(*idx);
+                }
+            }
+            (ranked, f64::from(ballot.count))
+        })
+        .filter(|(ranked, _)| !ranked.is_empty())
+        .collect();
+
+    let mut count = StvCount {
+        sorted_cands,
+        seats: rules.seats,
+        status: vec![Status::Hopeful; sorted_cands.len()],
+        elected: vec![],
+        rounds: vec![],
+    };
+    match rules.transfer {
+        SurplusTransfer::Gregory | SurplusTransfer::WeightedInclusiveGregory => {
+            count.run_gregory(&ballots, rules.transfer)
+        }
+        SurplusTransfer::Meek => count.run_meek(&ballots),
+    }
+
+    StvResult {
+        rules: *rules,
+        elected: count.elected.iter().map(|idx| sorted_cands[*idx]).collect(),
+        rounds: count.rounds,
+    }
+}
+
+struct StvCount<'a, 'b> {
+    sorted_cands: &'b [&'a str],
+    seats: usize,
+    status: Vec<Status>,
+    elected: Vec<usize>,
+    rounds: Vec<StvRound<'a>>,
+}
+
+impl<'a> StvCount<'a, '_> {
+    fn n_with_status(&self, status: Status) -> usize {
+        self.status.iter().filter(|s| **s == status).count()
+    }
+
+    /// The next hopeful candidate on a ballot after position `from`
+    fn next_hopeful(&self, ranked: &[usize], from: usize) -> Option<usize> {
+        ranked[from..]
+            .iter()
+            .position(|idx| self.status[*idx] == Status::Hopeful)
+            .map(|pos| from + pos)
+    }
+
+    fn new_round(&self, quota: f64, votes: &[f64], exhausted: f64) -> StvRound<'a> {
+        let mut tallies: Vec<(usize, f64)> = (0..self.sorted_cands.len())
+            .filter(|idx| self.status[*idx] != Status::Excluded)
+            .map(|idx| (idx, votes[idx]))
+            .collect();
+        tallies.sort_by(|a, b| b.1.total_cmp(&a.1));
+        StvRound {
+            round: self.rounds.len() + 1,
+            quota,
+            tallies: tallies
+                .iter()
+                .map(|(idx, v)| (self.sorted_cands[*idx], *v))
+                .collect(),
+            exhausted,
+            elected: vec![],
+            eliminated: vec![],
+            surplus_transfer: None,
+            keep_factors: vec![],
+        }
+    }
+
+    /// Elects every hopeful candidate that reached the quota, most votes first,
+    /// and returns them
+    fn elect_reaching_quota(
+        &mut self,
+        round: &mut StvRound<'a>,
+        votes: &[f64],
+        reached: impl Fn(f64) -> bool,
+    ) -> Vec<usize> {
+        let mut reaching: Vec<usize> = (0..self.sorted_cands.len())
+            .filter(|idx| self.status[*idx] == Status::Hopeful && reached(votes[*idx]))
+            .collect();
+        reaching.sort_by(|a, b| votes[*b].total_cmp(&votes[*a]));
+        reaching.truncate(self.seats - self.elected.len());
+        for idx in &reaching {
+            self.elect(round, *idx);
+        }
+        reaching
+    }
+
+    fn elect(&mut self, round: &mut StvRound<'a>, idx: usize) {
+        self.status[idx] = Status::Elected;
+        self.elected.push(idx);
+        round.elected.push(self.sorted_cands[idx]);
+    }
+
+    /// Whether every seat is filled. If only as many hopeful candidates are
+    /// left as there are seats to fill, they are all elected
+    fn finished(&mut self, round: &mut StvRound<'a>, votes: &[f64]) -> bool {
+        let open_seats = self.seats - self.elected.len();
+        if open_seats > 0 && self.n_with_status(Status::Hopeful) <= open_seats {
+            let mut hopeful: Vec<usize> = (0..self.sorted_cands.len())
+                .filter(|idx| self.status[*idx] == Status::Hopeful)
+                .collect();
+            hopeful.sort_by(|a, b| votes[*b].total_cmp(&votes[*a]));
+            for idx in hopeful {
+                self.elect(round, idx);
+            }
+        }
+        self.elected.len() >= self.seats || self.n_with_status(Status::Hopeful) == 0
+    }
+
+    /// The hopeful candidate with the fewest votes
+    fn lowest_hopeful(&self, votes: &[f64]) -> Option<usize> {
+        (0..self.sorted_cands.len())
+            .rev()
+            .filter(|idx| self.status[*idx] == Status::Hopeful)
+            .min_by(|a, b| votes[*a].total_cmp(&votes[*b]))
+    }
+
+    fn run_gregory(&mut self, ballots: &[(Vec<usize>, f64)], transfer: SurplusTransfer) {
+        let n_cands = self.sorted_cands.len();
+        let total: f64 = ballots.iter().map(|(_, weight)| weight).sum();
+        let quota = (total / (self.seats + 1) as f64).floor() + 1.;
+
+        // the position on the ballot of the candidate it counts for, and its weight
+        let mut positions: Vec<Option<usize>> = vec![None; ballots.len()];
+        let mut weights: Vec<f64> = ballots.iter().map(|(_, weight)| *weight).collect();
+        let mut votes = vec![0.; n_cands];
+        let mut exhausted = 0.;
+        // the ballots each candidate received in its last transfer, or first preferences
+        let mut last_parcels: Vec<Vec<usize>> = vec![vec![]; n_cands];
+        for (ballot_idx, (ranked, weight)) in ballots.iter().enumerate() {
+            positions[ballot_idx] = Some(0);
+            votes[ranked[0]] += weight;
+            last_parcels[ranked[0]].push(ballot_idx);
+        }
+
+        // elected candidates whose surplus has not been transferred yet
+        let mut surpluses: Vec<usize> = vec![];
+        loop {
+            let mut round = self.new_round(quota, &votes, exhausted);
+            surpluses.extend(self.elect_reaching_quota(&mut round, &votes, |v| v >= quota));
+            if self.finished(&mut round, &votes) {
+                self.rounds.push(round);
+                return;
+            }
+
+            // transfer the largest surplus, or else eliminate the weakest candidate
+            surpluses.sort_by(|a, b| votes[*b].total_cmp(&votes[*a]));
+            let (from, moving, factor) = if let Some(from) = surpluses.first().copied() {
+                surpluses.remove(0);
+                let moving: Vec<usize> = match transfer {
+                    SurplusTransfer::Gregory => last_parcels[from].clone(),
+                    _ => (0..ballots.len())
+                        .filter(|b| positions[*b].is_some_and(|pos| ballots[*b].0[pos] == from))
+                        .collect(),
+                };
+                let parcel: f64 = moving.iter().map(|b| weights[*b]).sum();
+                let surplus = votes[from] - quota;
+                let factor = if parcel > 0. {
+                    (surplus / parcel).min(1.)
+                } else {
+                    0.
+                };
+                round.surplus_transfer = Some((self.sorted_cands[from], factor));
+                votes[from] = quota;
+                (from, moving, factor)
+            } else {
+                let Some(loser) = self.lowest_hopeful(&votes) else {
+                    self.rounds.push(round);
+                    return;
+                };
+                self.status[loser] = Status::Excluded;
+                round.eliminated.push(self.sorted_cands[loser]);
+                let moving: Vec<usize> = (0..ballots.len())
+                    .filter(|b| positions[*b].is_some_and(|pos| ballots[*b].0[pos] == loser))
+                    .collect();
+                votes[loser] = 0.;
+                (loser, moving, 1.)
+            };
+
+            let mut received: HashMap<usize, Vec<usize>> = HashMap::new();
+            for ballot_idx in moving {
+                let ranked = &ballots[ballot_idx].0;
+                let Some(pos) = positions[ballot_idx] else {
+                    continue;
+                };
+                if ranked[pos] != from {
+                    continue;
+                }
+                weights[ballot_idx] *= factor;
+                match self.next_hopeful(ranked, pos + 1) {
+                    Some(next_pos) => {
+                        positions[ballot_idx] = Some(next_pos);
+                        votes[ranked[next_pos]] += weights[ballot_idx];
+                        received
+                            .entry(ranked[next_pos])
+                            .or_default()
+                            .push(ballot_idx);
+                    }
+                    None => {
+                        positions[ballot_idx] = None;
+                        exhausted += weights[ballot_idx];
+                    }
+                }
+            }
+            for (cand, parcel) in received {
+                last_parcels[cand] = parcel;
+            }
+            self.rounds.push(round);
+        }
+    }
+
+    fn run_meek(&mut self, ballots: &[(Vec<usize>, f64)]) {
+        let n_cands = self.sorted_cands.len();
+        let mut keep = vec![1.; n_cands];
+
+        loop {
+            // iterate the keep factors of the elected candidates until each keeps a quota
+            let (mut votes, mut exhausted, mut quota) = meek_distribute(ballots, &keep, self.seats);
+            for _ in 0..MEEK_MAX_ITERATIONS {
+                let converged = self
+                    .elected
+                    .iter()
+                    .all(|idx| (votes[*idx] - quota).abs() < MEEK_TOLERANCE);
+                if converged {
+                    break;
+                }
+                for idx in &self.elected {
+                    if votes[*idx] > 0. {
+                        keep[*idx] = (keep[*idx] * quota / votes[*idx]).min(1.);
+                    }
+                }
+                (votes, exhausted, quota) = meek_distribute(ballots, &keep, self.seats);
+            }
+
+            let mut round = self.new_round(quota, &votes, exhausted);
+            let newly_elected = self.elect_reaching_quota(&mut round, &votes, |v| v > quota);
+            let finished = self.finished(&mut round, &votes);
+            if !finished && newly_elected.is_empty() {
+                match self.lowest_hopeful(&votes) {
+                    Some(loser) => {
+                        self.status[loser] = Status::Excluded;
+                        keep[loser] = 0.;
+                        round.eliminated.push(self.sorted_cands[loser]);
+                    }
+                    None => {
+                        self.rounds.push(round);
+                        return;
+                    }
+                }
+            }
+            round.keep_factors = self
+                .elected
+                .iter()
+                .map(|idx| (self.sorted_cands[*idx], keep[*idx]))
+                .collect();
+            self.rounds.push(round);
+            if finished {
+                return;
+            }
+        }
+    }
+}
+
+/// Passes every ballot down its rankings, with each candidate keeping its keep
+/// factor of the weight reaching it. Returns the votes of every candidate, the
+/// exhausted votes, and the quota of the votes that are not exhausted
+fn meek_distribute(
+    ballots: &[(Vec<usize>, f64)],
+    keep: &[f64],
+    seats: usize,
+) -> (Vec<f64>, f64, f64) {
+    let mut votes = vec![0.; keep.len()];
+    let mut exhausted = 0.;
+    for (ranked, weight) in ballots {
+        let mut remaining = *weight;
+        for idx in ranked {
+            votes[*idx] += remaining * keep[*idx];
+            remaining *= 1. - keep[*idx];
+            if remaining <= 0. {
+                break;
+            }
+        }
+        exhausted += remaining;
+    }
+    let active: f64 = votes.iter().sum();
+    (votes, exhausted, active / (seats + 1) as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::Choice;
+
+    const CANDS: [&str; 4] = ["A", "B", "C", "D"];
+
+    fn ballot(ranks: &str, count: u32) -> WeightedBallot<'_> {
+        WeightedBallot {
+            choices: ranks.split(' ').map(Choice::Candidate).collect(),
+            count,
+        }
+    }
+
+    /// 2 seats and 30 votes, so the Droop quota is 11. C is eliminated first
+    /// and elects A. Only C's ballots move on with A's last parcel, while every
+    /// ballot of A moves on when all of them are transferred
+    fn ballots() -> Vec<WeightedBallot<'static>> {
+        vec![
+            ballot("A B", 10),
+            ballot("B", 8),
+            ballot("D", 7),
+            ballot("C A D", 5),
+        ]
+    }
+
+    fn stv(seats: usize, transfer: SurplusTransfer) -> StvResult<'static> {
+        tabulate_stv(&CANDS, &ballots(), &StvRules { seats, transfer })
+    }
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() < 1e-6, "{a} != {b}");
+    }
+
+    #[test]
+    fn gregory_transfers_the_last_parcel() {
+        let result = stv(2, SurplusTransfer::Gregory);
+        assert_eq!(result.rounds[0].eliminated, ["C"]);
+        assert_eq!(result.rounds[1].elected, ["A"]);
+        // A's surplus of 4 over the 5 ballots received from C
+        assert_eq!(result.rounds[1].surplus_transfer, Some(("A", 0.8)));
+        // elected candidates keep their quota in the tallies
+        assert_eq!(result.rounds[2].tallies[..2], [("A", 11.), ("D", 11.)]);
+        assert_eq!(result.elected, ["A", "D"]);
+    }
+
+    #[test]
+    fn weighted_inclusive_gregory_transfers_every_ballot() {
+        let result = stv(2, SurplusTransfer::WeightedInclusiveGregory);
+        let (from, factor) = result.rounds[1].surplus_transfer.unwrap();
+        assert_eq!(from, "A");
+        assert_close(factor, 4. / 15.);
+        let tallies = &result.rounds[2].tallies;
+        assert_eq!(tallies[1].0, "B");
+        assert_close(tallies[1].1, 8. + 10. * 4. / 15.);
+        assert_eq!(result.rounds[2].eliminated, ["D"]);
+        assert_eq!(result.elected, ["A", "B"]);
+    }
+
+    #[test]
+    fn meek_keeps_a_quota_for_each_elected_candidate() {
+        let result = stv(2, SurplusTransfer::Meek);
+        assert_eq!(result.rounds.len(), 3);
+        assert_eq!(result.rounds[0].eliminated, ["C"]);
+        assert_eq!(result.rounds[1].elected, ["A"]);
+
+        let last = &result.rounds[2];
+        assert_close(last.quota, 10.);
+        // B is elected in this round, so keeps all the votes reaching it
+        assert_eq!(last.keep_factors[0].0, "A");
+        assert_close(last.keep_factors[0].1, 2. / 3.);
+        assert_eq!(last.keep_factors[1], ("B", 1.));
+        assert_eq!(last.tallies[0].0, "B");
+        assert_close(last.tallies[0].1, 8. + 10. / 3.);
+        assert_eq!(result.elected, ["A", "B"]);
+    }
+
+    #[test]
+    fn no_seats_elects_nobody() {
+        for transfer in [SurplusTransfer::Gregory, SurplusTransfer::Meek] {
+            let result = stv(0, transfer);
+            assert!(result.elected.is_empty());
+            assert_eq!(result.rounds.len(), 1);
+        }
+    }
+
+    #[test]
+    fn every_candidate_is_elected_without_more_candidates_than_seats() {
+        for seats in [4, 5] {
+            for transfer in [
+                SurplusTransfer::Gregory,
+                SurplusTransfer::WeightedInclusiveGregory,
+                SurplusTransfer::Meek,
+            ] {
+                let result = stv(seats, transfer);
+                assert_eq!(result.rounds.len(), 1);
+                assert_eq!(result.elected, ["A", "B", "D", "C"]);
+            }
+        }
+    }
+}
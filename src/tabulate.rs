@@ -0,0 +1,530 @@
+use std::{cmp::Reverse, collections::HashMap};
+
+use serde::{Deserialize, Serialize};
+
+use crate::core::{Choice, SplitMix64, WeightedBallot};
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Elimination {
+    /// Eliminate only the candidate with the fewest votes each round
+    Single,
+    /// Eliminate every candidate that is mathematically defeated at once: the
+    /// lowest candidates whose combined votes are fewer than the votes of the
+    /// next candidate up
+    Batch,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case", tag = "method", deny_unknown_fields)]
+pub enum TieBreakRule {
+    /// Draw lots with a seeded PRNG, so the same seed always draws the same lots
+    Lot { seed: u64 },
+    /// The candidate with fewer votes in the first round loses the tie. Among
+    /// candidates also tied in the first round, the one later in the
+    /// candidate order loses
+    FirstPreferences,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum OvervotePolicy {
+    /// The ballot is exhausted once the count reaches the overvoted rank
+    ExhaustBallot,
+    /// The overvoted rank is passed over and the next rank is used
+    SkipRank,
+}
+
+/// The tabulation rules. Every field is written to the output, so that a count
+/// can be reproduced from the same `ballots.bin`
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct Rules {
+    pub elimination: Elimination,
+    pub tie_break: TieBreakRule,
+    pub overvote: OvervotePolicy,
+    /// The ballot is exhausted if more than this many consecutive ranks are
+    /// skipped before the next ranked candidate. `None` allows any number
+    pub max_skipped_ranks: Option<usize>,
+}
+
+impl Default for Rules {
+    /// The NYC Board of Elections rules
+    fn default() -> Self {
+        Self {
+            elimination: Elimination::Batch,
+            tie_break: TieBreakRule::Lot { seed: 0 },
+            overvote: OvervotePolicy::ExhaustBallot,
+            max_skipped_ranks: None,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct TieBreak<'a> {
+    pub tied: Vec<&'a str>,
+    /// The candidate that lost the tie when eliminating, or won it in the final round
+    pub chosen: &'a str,
+}
+
+#[derive(Serialize)]
+pub struct Round<'a> {
+    pub round: usize,
+    /// Votes for every continuing candidate, most votes first
+    pub tallies: Vec<(&'a str, u32)>,
+    /// Ballots counting towards a continuing candidate in this round
+    pub continuing_ballots: u32,
+    /// Ballots that no longer rank any continuing candidate, cumulative
+    pub exhausted_ballots: u32,
+    /// Candidates eliminated at the end of this round
+    pub eliminated: Vec<&'a str>,
+    /// Where the ballots of the eliminated candidates went in the next round
+    pub transfers: Vec<(&'a str, u32)>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tie_break: Option<TieBreak<'a>>,
+}
+
+#[derive(Serialize)]
+pub struct Tabulation<'a> {
+    pub rules: Rules,
+    pub winner: Option<&'a str>,
+    pub rounds: Vec<Round<'a>>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Mark {
+    Candidate(usize),
+    Overvote,
+    Undervote,
+}
+
+/// Runs an instant-runoff count following the NYC rules: a ballot counts for
+/// its highest ranked continuing candidate, ballots without one are exhausted,
+/// and the weakest candidates are eliminated until two remain.
+/// The candidate with the most votes in the final round wins.
+pub fn tabulate<'a>(
+    sorted_cands: &[&'a str],
+    all_ballots: &[WeightedBallot],
+    rules: &Rules,
+) -> Tabulation<'a> {
+    let mut count = IrvCount::new(sorted_cands, all_ballots, rules);
+    let mut rounds = vec![];
+    while !count.is_finished() {
+        rounds.push(count.round());
+    }
+    Tabulation {
+        rules: *rules,
+        winner: count.winner(),
+        rounds,
+    }
+}
+
+/// An instant-runoff count in progress. `tabulate` runs it to the end, and the
+/// hybrid methods also eliminate candidates between its rounds
+pub struct IrvCount<'a> {
+    sorted_cands: Vec<&'a str>,
+    rules: Rules,
+    ballots: Vec<Vec<Mark>>,
+    weights: Vec<u32>,
+    continuing: Vec<bool>,
+    /// The candidate each ballot is currently counting towards
+    current: Vec<Option<usize>>,
+    lot: Option<SplitMix64>,
+    /// Votes of every candidate in the first round, to break ties on
+    first_round: Vec<u32>,
+    n_rounds: usize,
+    winner: Option<Option<&'a str>>,
+}
+
+impl<'a> IrvCount<'a> {
+    pub fn new(sorted_cands: &[&'a str], all_ballots: &[WeightedBallot], rules: &Rules) -> Self {
+        let cand_to_idx: HashMap<&str, usize> = sorted_cands
+            .iter()
+            .enumerate()
+            .map(|(idx, cand)| (*cand, idx))
+            .collect();
+
+        // work with candidate indexes so that each round doesn't need to hash names
+        let ballots: Vec<Vec<Mark>> = all_ballots
+            .iter()
+            .map(|ballot| {
+                ballot
+                    .choices
+                    .iter()
+                    .map(|choice| match choice {
+                        Choice::Candidate(cand) => cand_to_idx
+                            .get(cand)
+                            .map_or(Mark::Undervote, |idx| Mark::Candidate(*idx)),
+                        Choice::Overvote => Mark::Overvote,
+                        // since none of the write-ins were important, they are skipped like undervotes
+                        Choice::Undervote | Choice::WriteIn => Mark::Undervote,
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let continuing = vec![true; sorted_cands.len()];
+        let current = ballots
+            .iter()
+            .map(|ballot| next_continuing(ballot, &continuing, rules))
+            .collect();
+
+        let mut count = Self {
+            sorted_cands: sorted_cands.to_vec(),
+            rules: *rules,
+            ballots,
+            weights: all_ballots.iter().map(|ballot| ballot.count).collect(),
+            continuing,
+            current,
+            lot: match rules.tie_break {
+                TieBreakRule::Lot { seed } => Some(SplitMix64::new(seed)),
+                TieBreakRule::FirstPreferences => None,
+            },
+            first_round: vec![],
+            n_rounds: 0,
+            winner: None,
+        };
+        (count.first_round, _) = count.votes();
+        count
+    }
+
+    /// The continuing candidates, in candidate order
+    pub fn continuing(&self) -> Vec<&'a str> {
+        self.sorted_cands
+            .iter()
+            .zip(&self.continuing)
+            .filter(|(_, is_continuing)| **is_continuing)
+            .map(|(cand, _)| *cand)
+            .collect()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.winner.is_some()
+    }
+
+    /// The winner once the count is finished
+    pub fn winner(&self) -> Option<&'a str> {
+        self.winner.flatten()
+    }
+
+    /// Counts a round, then eliminates its weakest candidates, or elects the
+    /// winner when at most two candidates are left
+    pub fn round(&mut self) -> Round<'a> {
+        let (votes, exhausted_ballots) = self.votes();
+
+        let mut tallies: Vec<(usize, u32)> = self
+            .continuing
+            .iter()
+            .enumerate()
+            .filter(|(_, is_continuing)| **is_continuing)
+            .map(|(idx, _)| (idx, votes[idx]))
+            .collect();
+        // stable sort, so ties are kept in the order of `sorted_cands`
+        tallies.sort_by_key(|(_, v)| Reverse(*v));
+
+        let continuing_ballots = tallies.iter().map(|(_, v)| v).sum();
+
+        self.n_rounds += 1;
+        let mut round = Round {
+            round: self.n_rounds,
+            tallies: tallies
+                .iter()
+                .map(|(idx, v)| (self.sorted_cands[*idx], *v))
+                .collect(),
+            continuing_ballots,
+            exhausted_ballots,
+            eliminated: vec![],
+            transfers: vec![],
+            tie_break: None,
+        };
+
+        if tallies.len() <= 2 {
+            let winner = match tallies.as_slice() {
+                [] => None,
+                [(a, a_votes), (b, b_votes)] if a_votes == b_votes => {
+                    let tied = [*b, *a];
+                    let winner = self.break_tie(&tied, Chosen::Wins);
+                    round.tie_break = Some(TieBreak {
+                        tied: tied.iter().map(|idx| self.sorted_cands[*idx]).collect(),
+                        chosen: self.sorted_cands[winner],
+                    });
+                    Some(self.sorted_cands[winner])
+                }
+                [(idx, _), ..] => Some(self.sorted_cands[*idx]),
+            };
+            self.winner = Some(winner);
+            return round;
+        }
+
+        let losers = match self.rules.elimination {
+            Elimination::Batch => batch_losers(&tallies),
+            Elimination::Single => vec![],
+        };
+        let losers = if losers.is_empty() {
+            let (_, fewest) = tallies[tallies.len() - 1];
+            let tied: Vec<usize> = tallies
+                .iter()
+                .filter(|(_, v)| *v == fewest)
+                .map(|(idx, _)| *idx)
+                .collect();
+            let loser = self.break_tie(&tied, Chosen::Loses);
+            if tied.len() > 1 {
+                round.tie_break = Some(TieBreak {
+                    tied: tied.iter().map(|idx| self.sorted_cands[*idx]).collect(),
+                    chosen: self.sorted_cands[loser],
+                });
+            }
+            vec![loser]
+        } else {
+            losers
+        };
+
+        round.eliminated = losers.iter().map(|idx| self.sorted_cands[*idx]).collect();
+        round.transfers = self.eliminate_indexes(&losers);
+        round
+    }
+
+    /// Eliminates candidates outside of a round. Candidates that aren't
+    /// continuing are ignored
+    pub fn eliminate(&mut self, cands: &[&str]) {
+        let losers: Vec<usize> = cands
+            .iter()
+            .filter_map(|cand| self.sorted_cands.iter().position(|c| c == cand))
+            .collect();
+        self.eliminate_indexes(&losers);
+    }
+
+    /// Votes of every candidate, and the number of exhausted ballots
+    fn votes(&self) -> (Vec<u32>, u32) {
+        let mut votes = vec![0_u32; self.sorted_cands.len()];
+        let mut exhausted_ballots = 0;
+        for (count, choice) in self.weights.iter().zip(&self.current) {
+            match choice {
+                Some(idx) => votes[*idx] += count,
+                None => exhausted_ballots += count,
+            }
+        }
+        (votes, exhausted_ballots)
+    }
+
+    /// Moves the ballots of the losers to their next continuing candidate, and
+    /// returns where they went
+    fn eliminate_indexes(&mut self, losers: &[usize]) -> Vec<(&'a str, u32)> {
+        for loser in losers {
+            self.continuing[*loser] = false;
+        }
+
+        let mut transfers: HashMap<Option<usize>, u32> = HashMap::new();
+        for ((ballot, count), choice) in self
+            .ballots
+            .iter()
+            .zip(&self.weights)
+            .zip(self.current.iter_mut())
+        {
+            if choice.is_some_and(|idx| !self.continuing[idx]) {
+                *choice = next_continuing(ballot, &self.continuing, &self.rules);
+                *transfers.entry(*choice).or_insert(0) += count;
+            }
+        }
+
+        let mut transfers: Vec<_> = transfers
+            .into_iter()
+            .map(|(to, n)| (to.map_or("Exhausted", |idx| self.sorted_cands[idx]), n))
+            .collect();
+        transfers.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        transfers
+    }
+
+    /// Chooses one of the tied candidates. Without a lot, the candidate with
+    /// the most or fewest votes in the first round is chosen, and among those
+    /// the candidate later in the candidate order loses
+    fn break_tie(&mut self, tied: &[usize], chosen: Chosen) -> usize {
+        let key = |idx: &usize| (self.first_round[*idx], Reverse(*idx));
+        match (&mut self.lot, chosen) {
+            (Some(lot), _) if tied.len() > 1 => tied[lot.below(tied.len())],
+            (_, Chosen::Wins) => tied.iter().copied().max_by_key(key).unwrap_or(tied[0]),
+            (_, Chosen::Loses) => tied.iter().copied().min_by_key(key).unwrap_or(tied[0]),
+        }
+    }
+}
+
+fn next_continuing(ballot: &[Mark], continuing: &[bool], rules: &Rules) -> Option<usize> {
+    let mut skipped = 0;
+    for mark in ballot {
+        match mark {
+            Mark::Candidate(idx) => {
+                if rules.max_skipped_ranks.is_some_and(|max| skipped > max) {
+                    return None;
+                }
+                if continuing[*idx] {
+                    return Some(*idx);
+                }
+                skipped = 0;
+            }
+            Mark::Overvote => match rules.overvote {
+                OvervotePolicy::ExhaustBallot => return None,
+                OvervotePolicy::SkipRank => (),
+            },
+            Mark::Undervote => skipped += 1,
+        }
+    }
+    None
+}
+
+/// Finds every candidate that cannot win even if it received all the votes of
+/// the candidates below it. At least two candidates are always left continuing.
+/// `tallies` must be sorted by most votes first.
+fn batch_losers(tallies: &[(usize, u32)]) -> Vec<usize> {
+    let ascending: Vec<_> = tallies.iter().rev().collect();
+    let mut n_losers = 0;
+    let mut cumulative = 0;
+    for idx in 0..ascending.len() - 2 {
+        cumulative += ascending[idx].1;
+        if cumulative < ascending[idx + 1].1 {
+            n_losers = idx + 1;
+        }
+    }
+    ascending[..n_losers].iter().map(|(idx, _)| *idx).collect()
+}
+
+/// Whether the candidate chosen by a tie break wins or loses the tie
+#[derive(Clone, Copy)]
+enum Chosen {
+    Wins,
+    Loses,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A ballot from its ranks separated by spaces: `-` is an undervote and
+    /// `=` an overvote
+    fn ballot(ranks: &'static str, count: u32) -> WeightedBallot<'static> {
+        let choices = ranks
+            .split(' ')
+            .map(|rank| match rank {
+                "-" => Choice::Undervote,
+                "=" => Choice::Overvote,
+                cand => Choice::Candidate(cand),
+            })
+            .collect();
+        WeightedBallot { choices, count }
+    }
+
+    fn rules(tie_break: TieBreakRule) -> Rules {
+        Rules {
+            tie_break,
+            ..Rules::default()
+        }
+    }
+
+    #[test]
+    fn batch_eliminates_every_defeated_candidate() {
+        let ballots = [
+            ballot("A", 10),
+            ballot("B", 9),
+            ballot("C B", 3),
+            ballot("D B", 2),
+        ];
+        let cands = ["A", "B", "C", "D"];
+
+        let batch = tabulate(&cands, &ballots, &Rules::default());
+        assert_eq!(batch.rounds.len(), 2);
+        assert_eq!(batch.rounds[0].eliminated, ["D", "C"]);
+        assert_eq!(batch.rounds[1].tallies, [("B", 14), ("A", 10)]);
+        assert_eq!(batch.winner, Some("B"));
+
+        let single = Rules {
+            elimination: Elimination::Single,
+            ..Rules::default()
+        };
+        let single = tabulate(&cands, &ballots, &single);
+        assert_eq!(single.rounds.len(), 3);
+        assert_eq!(single.rounds[0].eliminated, ["D"]);
+        assert_eq!(single.rounds[1].eliminated, ["C"]);
+        assert_eq!(single.winner, Some("B"));
+    }
+
+    #[test]
+    fn final_round_tie_goes_to_more_first_preferences() {
+        let ballots = [ballot("A", 5), ballot("B", 4), ballot("C B", 1)];
+        // the candidate order doesn't follow the first preferences
+        let tabulation = tabulate(
+            &["B", "A", "C"],
+            &ballots,
+            &rules(TieBreakRule::FirstPreferences),
+        );
+        let last = &tabulation.rounds[1];
+        assert_eq!(last.tallies, [("B", 5), ("A", 5)]);
+        assert_eq!(last.tie_break.as_ref().map(|t| t.chosen), Some("A"));
+        assert_eq!(tabulation.winner, Some("A"));
+
+        let tabulation = tabulate(
+            &["B", "A", "C"],
+            &ballots,
+            &rules(TieBreakRule::Lot { seed: 7 }),
+        );
+        let tie_break = tabulation.rounds[1].tie_break.as_ref();
+        assert_eq!(tie_break.map(|t| t.chosen), tabulation.winner);
+    }
+
+    #[test]
+    fn elimination_tie_loser_has_fewer_first_preferences() {
+        let ballots = [
+            ballot("A", 10),
+            ballot("B", 3),
+            ballot("C", 2),
+            ballot("D C", 1),
+        ];
+        let single = Rules {
+            elimination: Elimination::Single,
+            ..rules(TieBreakRule::FirstPreferences)
+        };
+        let tabulation = tabulate(&["A", "C", "B", "D"], &ballots, &single);
+        let tied = &tabulation.rounds[1];
+        assert_eq!(tied.tallies, [("A", 10), ("C", 3), ("B", 3)]);
+        assert_eq!(tied.eliminated, ["C"]);
+    }
+
+    #[test]
+    fn overvote_exhausts_or_is_skipped() {
+        let ballots = [ballot("A", 4), ballot("B", 3), ballot("= B", 2)];
+        let cands = ["A", "B"];
+
+        let exhaust = tabulate(&cands, &ballots, &Rules::default());
+        assert_eq!(exhaust.rounds[0].tallies, [("A", 4), ("B", 3)]);
+        assert_eq!(exhaust.rounds[0].exhausted_ballots, 2);
+        assert_eq!(exhaust.winner, Some("A"));
+
+        let skip = Rules {
+            overvote: OvervotePolicy::SkipRank,
+            ..Rules::default()
+        };
+        let skip = tabulate(&cands, &ballots, &skip);
+        assert_eq!(skip.rounds[0].tallies, [("B", 5), ("A", 4)]);
+        assert_eq!(skip.rounds[0].exhausted_ballots, 0);
+        assert_eq!(skip.winner, Some("B"));
+    }
+
+    #[test]
+    fn too_many_skipped_ranks_exhaust_the_ballot() {
+        let ballots = [ballot("A", 4), ballot("B", 3), ballot("- - B", 2)];
+        let cands = ["A", "B"];
+        let max_skipped = |max_skipped_ranks| Rules {
+            max_skipped_ranks,
+            ..Rules::default()
+        };
+
+        let strict = tabulate(&cands, &ballots, &max_skipped(Some(1)));
+        assert_eq!(strict.rounds[0].exhausted_ballots, 2);
+        assert_eq!(strict.winner, Some("A"));
+
+        let lenient = tabulate(&cands, &ballots, &max_skipped(Some(2)));
+        assert_eq!(lenient.rounds[0].exhausted_ballots, 0);
+        assert_eq!(lenient.winner, Some("B"));
+
+        let unlimited = tabulate(&cands, &ballots, &max_skipped(None));
+        assert_eq!(unlimited.winner, Some("B"));
+    }
+}